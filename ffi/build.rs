@@ -0,0 +1,21 @@
+// With the `header` feature, generates beebc.h from the extern "C"
+// functions in src/lib.rs into OUT_DIR, and warns if the copy kept in
+// include/ is out of date. The source tree itself is never written.
+#[cfg(feature = "header")]
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out = format!("{}/beebc.h", std::env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=include/beebc.h");
+    cbindgen::generate(&crate_dir)
+        .expect("unable to generate beebc.h")
+        .write_to_file(&out);
+    let kept = std::fs::read_to_string(format!("{}/include/beebc.h", crate_dir)).unwrap_or_default();
+    if std::fs::read_to_string(&out).unwrap_or_default() != kept {
+        println!("cargo:warning=include/beebc.h is out of date, copy it from {}", out);
+    }
+}
+
+#[cfg(not(feature = "header"))]
+fn main() {}
@@ -0,0 +1,158 @@
+#ifndef BEEBC_H
+#define BEEBC_H
+
+/* Generated by cbindgen from ffi/src/lib.rs. Do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Success.
+#define BEEBC_OK 0
+
+// An undefined op code was executed.
+#define BEEBC_UNDEFINED_OPCODE 1
+
+// The PC ran past the end of ram.
+#define BEEBC_PC_OVERFLOW 2
+
+// A program image was the wrong size.
+#define BEEBC_PROGRAM_SIZE 3
+
+// A ram access rule was broken (see beebc_protect).
+#define BEEBC_PROTECTED 4
+
+// A pointer was NULL, or a setting unknown. Never kept for beebc_last_error.
+#define BEEBC_BAD_ARGUMENT 5
+
+typedef struct BeebcMachine BeebcMachine;
+
+typedef struct BeebcRegisters {
+  uint8_t pc;
+  uint8_t sc;
+  uint8_t mar;
+  uint8_t ir;
+  uint8_t reg_a;
+  uint8_t reg_b;
+  uint8_t reg_alu;
+  uint8_t reg_out;
+  uint8_t reg_flgs;
+  uint8_t bus;
+  uint8_t reg_in;
+  uint8_t epc;
+  bool ie;
+  bool irq;
+  bool hlt;
+} BeebcRegisters;
+
+// Creates a machine with zeroed registers and ram. Free it with beebc_free.
+struct BeebcMachine *beebc_new(void);
+
+// Frees a machine made by beebc_new.
+//
+// # Safety
+// `m` must be NULL or a pointer from beebc_new that has not been freed.
+void beebc_free(struct BeebcMachine *m);
+
+// Loads a program image into ram. The image must fill ram, 16 bytes.
+// Returns BEEBC_OK, BEEBC_BAD_ARGUMENT if a pointer is NULL, or
+// BEEBC_PROGRAM_SIZE if `len` is not 16 - ram is left as it was.
+//
+// # Safety
+// `m` must come from beebc_new, and `data` must point to `len` readable bytes.
+int32_t beebc_load(struct BeebcMachine *m, const uint8_t *data, size_t len);
+
+// Advances the clock one tick. Returns BEEBC_OK, the error that stopped
+// the tick, or BEEBC_BAD_ARGUMENT if `m` is NULL. After a tick, the control
+// word it applied is written to `cw`, unless that is NULL.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new, and `cw` must be NULL or writable.
+int32_t beebc_step(struct BeebcMachine *m, uint32_t *cw);
+
+// Ticks the clock until the machine halts, a tick fails, or `max_ticks`
+// have run. A `max_ticks` of 0 means no limit. Returns the number of ticks run.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+uint64_t beebc_run(struct BeebcMachine *m, uint64_t max_ticks);
+
+// Whether the machine has executed HLT.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+bool beebc_halted(const struct BeebcMachine *m);
+
+// Copies the machine's registers into `out`.
+//
+// # Safety
+// `m` must come from beebc_new, and `out` must be writable.
+void beebc_registers(const struct BeebcMachine *m, struct BeebcRegisters *out);
+
+// Copies up to `cap` of the values latched into the output register so far
+// into `buf`, oldest first. Returns the total number of values output, which
+// may be more than `cap`. `buf` may be NULL to just ask for the count.
+//
+// # Safety
+// `m` must come from beebc_new, and `buf` must be NULL or point to `cap` writable bytes.
+size_t beebc_outputs(const struct BeebcMachine *m, uint8_t *buf, size_t cap);
+
+// Forgets the values output so far.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+void beebc_clear_outputs(struct BeebcMachine *m);
+
+// Why the last beebc_step or beebc_run stopped early, or beebc_load failed,
+// as one of the status codes. BEEBC_OK if nothing has gone wrong.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+int32_t beebc_last_error(const struct BeebcMachine *m);
+
+// Sets what undefined op codes do. 0 - trap (the default), 1 - run as NOP,
+// 2 - halt. Returns BEEBC_OK, or BEEBC_BAD_ARGUMENT for an unknown policy.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+int32_t beebc_set_undefined(struct BeebcMachine *m, int32_t policy);
+
+// Sets what happens when the PC runs past the end of ram. 0 - trap (the
+// default), 1 - wrap round to address 0, 2 - halt. Returns BEEBC_OK, or
+// BEEBC_BAD_ARGUMENT for an unknown policy.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+int32_t beebc_set_pc_overflow(struct BeebcMachine *m, int32_t policy);
+
+// Turns the signed flags extension - N and V flags, JN and JV - on or off.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+void beebc_set_nv_flags(struct BeebcMachine *m, bool on);
+
+// Sets the ram access rules - a bit per address in each mask. Writes to
+// `read_only` cells, reads and writes of `exec_only` cells as data, and
+// fetches from `no_exec` cells stop the machine with BEEBC_PROTECTED.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+void beebc_protect(struct BeebcMachine *m,
+                   uint16_t read_only,
+                   uint16_t exec_only,
+                   uint16_t no_exec);
+
+// Presses the reset button. Clears the registers, but not ram.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+void beebc_reset(struct BeebcMachine *m);
+
+// Fills the registers, and every ram cell whose bit is clear in `keep`,
+// with random values made from `seed`. The PC and step counter start at 0.
+//
+// # Safety
+// `m` must be NULL or come from beebc_new.
+void beebc_power_on(struct BeebcMachine *m, uint16_t keep, uint64_t seed);
+
+#endif /* BEEBC_H */
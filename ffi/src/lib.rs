@@ -0,0 +1,439 @@
+// C-compatible interface to the beebc emulator core.
+//
+// The header, include/beebc.h, is kept in the tree. Building with
+// --features header regenerates it with cbindgen, and warns if it differs.
+// Every function taking a machine pointer expects one made by
+// beebc_new, and does nothing (or returns 0) when handed NULL. Functions
+// that can fail return one of the status codes below, 0 for success.
+use std::slice;
+use rustybeebc::beebc;
+use rustybeebc::beebc::error::{BeebcError, Overflow, Undefined};
+use rustybeebc::beebc::protect::Protection;
+
+/// Success.
+pub const BEEBC_OK: i32 = 0;
+/// An undefined op code was executed.
+pub const BEEBC_UNDEFINED_OPCODE: i32 = 1;
+/// The PC ran past the end of ram.
+pub const BEEBC_PC_OVERFLOW: i32 = 2;
+/// A program image was the wrong size.
+pub const BEEBC_PROGRAM_SIZE: i32 = 3;
+/// A ram access rule was broken (see beebc_protect).
+pub const BEEBC_PROTECTED: i32 = 4;
+/// A pointer was NULL, or a setting unknown. Never kept for beebc_last_error.
+pub const BEEBC_BAD_ARGUMENT: i32 = 5;
+
+// Opaque handle to one machine, its ram and the values it has output.
+pub struct BeebcMachine {
+    ebc:     beebc::EBC,
+    ram:     [u8; 16],
+    outputs: Vec<u8>,
+    error:   Option<BeebcError>,
+}
+
+// Snapshot of the machine's registers.
+#[repr(C)]
+pub struct BeebcRegisters {
+    pub pc:       u8,
+    pub sc:       u8,
+    pub mar:      u8,
+    pub ir:       u8,
+    pub reg_a:    u8,
+    pub reg_b:    u8,
+    pub reg_alu:  u8,
+    pub reg_out:  u8,
+    pub reg_flgs: u8,
+    pub bus:      u8,
+    pub reg_in:   u8,
+    pub epc:      u8,
+    pub ie:       bool,
+    pub irq:      bool,
+    pub hlt:      bool,
+}
+
+/// Creates a machine with zeroed registers and ram. Free it with beebc_free.
+#[no_mangle]
+pub extern "C" fn beebc_new() -> *mut BeebcMachine {
+    Box::into_raw(Box::new(BeebcMachine {
+        ebc: beebc::EBC::default(),
+        ram: [0; 16],
+        outputs: Vec::new(),
+        error: None,
+    }))
+}
+
+/// Frees a machine made by beebc_new.
+///
+/// # Safety
+/// `m` must be NULL or a pointer from beebc_new that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_free(m: *mut BeebcMachine) {
+    if !m.is_null() {
+        drop(Box::from_raw(m));
+    }
+}
+
+/// Loads a program image into ram. The image must fill ram, 16 bytes.
+/// Returns BEEBC_OK, BEEBC_BAD_ARGUMENT if a pointer is NULL, or
+/// BEEBC_PROGRAM_SIZE if `len` is not 16 - ram is left as it was.
+///
+/// # Safety
+/// `m` must come from beebc_new, and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_load(m: *mut BeebcMachine, data: *const u8, len: usize) -> i32 {
+    let m = match m.as_mut() {
+        Some(m) => m,
+        None => return BEEBC_BAD_ARGUMENT,
+    };
+    if data.is_null() {
+        return BEEBC_BAD_ARGUMENT;
+    }
+    match beebc::load(&mut m.ram, slice::from_raw_parts(data, len)) {
+        Ok(()) => BEEBC_OK,
+        Err(e) => {
+            m.error = Some(e);
+            error_code(e)
+        },
+    }
+}
+
+/// Advances the clock one tick. Returns BEEBC_OK, the error that stopped
+/// the tick, or BEEBC_BAD_ARGUMENT if `m` is NULL. After a tick, the control
+/// word it applied is written to `cw`, unless that is NULL.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new, and `cw` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_step(m: *mut BeebcMachine, cw: *mut u32) -> i32 {
+    let m = match m.as_mut() {
+        Some(m) => m,
+        None => return BEEBC_BAD_ARGUMENT,
+    };
+    match step(m) {
+        Ok(word) => {
+            if let Some(cw) = cw.as_mut() {
+                *cw = word.0;
+            }
+            BEEBC_OK
+        },
+        Err(e) => error_code(e),
+    }
+}
+
+/// Ticks the clock until the machine halts, a tick fails, or `max_ticks`
+/// have run. A `max_ticks` of 0 means no limit. Returns the number of ticks run.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_run(m: *mut BeebcMachine, max_ticks: u64) -> u64 {
+    let m = match m.as_mut() {
+        Some(m) => m,
+        None => return 0,
+    };
+    let mut ticks = 0;
+    while !m.ebc.hlt && (max_ticks == 0 || ticks < max_ticks) {
+        if step(m).is_err() {
+            break;
+        }
+        ticks += 1;
+    }
+    ticks
+}
+
+/// Whether the machine has executed HLT.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_halted(m: *const BeebcMachine) -> bool {
+    match m.as_ref() {
+        Some(m) => m.ebc.hlt,
+        None => false,
+    }
+}
+
+/// Copies the machine's registers into `out`.
+///
+/// # Safety
+/// `m` must come from beebc_new, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_registers(m: *const BeebcMachine, out: *mut BeebcRegisters) {
+    let (m, out) = match (m.as_ref(), out.as_mut()) {
+        (Some(m), Some(out)) => (m, out),
+        _ => return,
+    };
+    let e = &m.ebc;
+    *out = BeebcRegisters {
+        pc: e.pc,
+        sc: e.sc,
+        mar: e.mar,
+        ir: e.ir,
+        reg_a: e.reg_a,
+        reg_b: e.reg_b,
+        reg_alu: e.reg_alu,
+        reg_out: e.reg_out,
+        reg_flgs: e.reg_flgs,
+        bus: e.bus,
+        reg_in: e.reg_in,
+        epc: e.epc,
+        ie: e.ie,
+        irq: e.irq,
+        hlt: e.hlt,
+    };
+}
+
+/// Copies up to `cap` of the values latched into the output register so far
+/// into `buf`, oldest first. Returns the total number of values output, which
+/// may be more than `cap`. `buf` may be NULL to just ask for the count.
+///
+/// # Safety
+/// `m` must come from beebc_new, and `buf` must be NULL or point to `cap` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_outputs(m: *const BeebcMachine, buf: *mut u8, cap: usize) -> usize {
+    let m = match m.as_ref() {
+        Some(m) => m,
+        None => return 0,
+    };
+    if !buf.is_null() {
+        let n = cap.min(m.outputs.len());
+        slice::from_raw_parts_mut(buf, n).copy_from_slice(&m.outputs[..n]);
+    }
+    m.outputs.len()
+}
+
+/// Forgets the values output so far.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_clear_outputs(m: *mut BeebcMachine) {
+    if let Some(m) = m.as_mut() {
+        m.outputs.clear();
+    }
+}
+
+/// Why the last beebc_step or beebc_run stopped early, or beebc_load failed,
+/// as one of the status codes. BEEBC_OK if nothing has gone wrong.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_last_error(m: *const BeebcMachine) -> i32 {
+    m.as_ref().and_then(|m| m.error).map_or(BEEBC_OK, error_code)
+}
+
+/// Sets what undefined op codes do. 0 - trap (the default), 1 - run as NOP,
+/// 2 - halt. Returns BEEBC_OK, or BEEBC_BAD_ARGUMENT for an unknown policy.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_set_undefined(m: *mut BeebcMachine, policy: i32) -> i32 {
+    let m = match m.as_mut() {
+        Some(m) => m,
+        None => return BEEBC_BAD_ARGUMENT,
+    };
+    m.ebc.undef = match policy {
+        0 => Undefined::Trap,
+        1 => Undefined::Nop,
+        2 => Undefined::Halt,
+        _ => return BEEBC_BAD_ARGUMENT,
+    };
+    BEEBC_OK
+}
+
+/// Sets what happens when the PC runs past the end of ram. 0 - trap (the
+/// default), 1 - wrap round to address 0, 2 - halt. Returns BEEBC_OK, or
+/// BEEBC_BAD_ARGUMENT for an unknown policy.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_set_pc_overflow(m: *mut BeebcMachine, policy: i32) -> i32 {
+    let m = match m.as_mut() {
+        Some(m) => m,
+        None => return BEEBC_BAD_ARGUMENT,
+    };
+    m.ebc.overflow = match policy {
+        0 => Overflow::Trap,
+        1 => Overflow::Wrap,
+        2 => Overflow::Halt,
+        _ => return BEEBC_BAD_ARGUMENT,
+    };
+    BEEBC_OK
+}
+
+/// Turns the signed flags extension - N and V flags, JN and JV - on or off.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_set_nv_flags(m: *mut BeebcMachine, on: bool) {
+    if let Some(m) = m.as_mut() {
+        m.ebc.nv_flags = on;
+    }
+}
+
+/// Sets the ram access rules - a bit per address in each mask. Writes to
+/// `read_only` cells, reads and writes of `exec_only` cells as data, and
+/// fetches from `no_exec` cells stop the machine with BEEBC_PROTECTED.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_protect(m: *mut BeebcMachine, read_only: u16, exec_only: u16, no_exec: u16) {
+    if let Some(m) = m.as_mut() {
+        m.ebc.protect = Protection { read_only, exec_only, no_exec, warn: false };
+    }
+}
+
+/// Presses the reset button. Clears the registers, but not ram.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_reset(m: *mut BeebcMachine) {
+    if let Some(m) = m.as_mut() {
+        beebc::power::reset(&mut m.ebc);
+        m.error = None;
+    }
+}
+
+/// Fills the registers, and every ram cell whose bit is clear in `keep`,
+/// with random values made from `seed`. The PC and step counter start at 0.
+///
+/// # Safety
+/// `m` must be NULL or come from beebc_new.
+#[no_mangle]
+pub unsafe extern "C" fn beebc_power_on(m: *mut BeebcMachine, keep: u16, seed: u64) {
+    if let Some(m) = m.as_mut() {
+        beebc::power::power_on(&mut m.ebc, &mut m.ram, keep, seed);
+        m.error = None;
+    }
+}
+
+fn error_code(e: BeebcError) -> i32 {
+    match e {
+        BeebcError::UndefinedOpcode { .. } => BEEBC_UNDEFINED_OPCODE,
+        BeebcError::PcOverflow => BEEBC_PC_OVERFLOW,
+        BeebcError::ProgramSize { .. } => BEEBC_PROGRAM_SIZE,
+        BeebcError::Protected { .. } => BEEBC_PROTECTED,
+    }
+}
+
+fn step(m: &mut BeebcMachine) -> Result<beebc::signal::ControlWord, BeebcError> {
+    let result = beebc::step(&mut m.ebc, &mut m.ram);
+    m.error = result.err();
+    let cw = result?;
+    if cw.has(beebc::signal::OI) {
+        m.outputs.push(m.ebc.reg_out);
+    }
+    Ok(cw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    // LDI 7, OUT, HLT.
+    const PROG: [u8; 16] = [0x57, 0xE0, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn runs_a_program() {
+        unsafe {
+            let m = beebc_new();
+            assert_eq!(beebc_load(m, PROG.as_ptr(), PROG.len()), BEEBC_OK);
+            assert!(beebc_run(m, 0) > 0);
+            assert!(beebc_halted(m));
+            let mut out = [0u8; 4];
+            assert_eq!(beebc_outputs(m, out.as_mut_ptr(), out.len()), 1);
+            assert_eq!(out[0], 7);
+            assert_eq!(beebc_last_error(m), BEEBC_OK);
+            beebc_free(m);
+        }
+    }
+
+    #[test]
+    fn steps_give_the_control_word() {
+        unsafe {
+            let m = beebc_new();
+            beebc_load(m, PROG.as_ptr(), PROG.len());
+            let mut cw = u32::MAX;
+            assert_eq!(beebc_step(m, &mut cw), BEEBC_OK);
+            assert_eq!(cw, (beebc::signal::MI | beebc::signal::CO).0);
+            assert_eq!(beebc_step(m, ptr::null_mut()), BEEBC_OK);
+            // Idle steps apply control word 0, and are not errors.
+            for _ in 0..6 {
+                assert_eq!(beebc_step(m, &mut cw), BEEBC_OK);
+            }
+            assert_eq!(cw, 0);
+            beebc_free(m);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let m = beebc_new();
+            assert_eq!(beebc_load(m, PROG.as_ptr(), 4), BEEBC_PROGRAM_SIZE);
+            assert_eq!(beebc_last_error(m), BEEBC_PROGRAM_SIZE);
+            // All NOPs - runs off the end of ram.
+            beebc_run(m, 0);
+            assert_eq!(beebc_last_error(m), BEEBC_PC_OVERFLOW);
+            assert_eq!(beebc_step(m, ptr::null_mut()), BEEBC_PC_OVERFLOW);
+            beebc_reset(m);
+            assert_eq!(beebc_last_error(m), BEEBC_OK);
+            beebc_free(m);
+        }
+    }
+
+    #[test]
+    fn bad_arguments() {
+        unsafe {
+            let m = beebc_new();
+            assert_eq!(beebc_load(ptr::null_mut(), PROG.as_ptr(), 16), BEEBC_BAD_ARGUMENT);
+            assert_eq!(beebc_load(m, ptr::null(), 16), BEEBC_BAD_ARGUMENT);
+            assert_eq!(beebc_step(ptr::null_mut(), ptr::null_mut()), BEEBC_BAD_ARGUMENT);
+            assert_eq!(beebc_set_undefined(m, 3), BEEBC_BAD_ARGUMENT);
+            assert_eq!(beebc_set_pc_overflow(m, -1), BEEBC_BAD_ARGUMENT);
+            assert_eq!(beebc_last_error(m), BEEBC_OK);
+            beebc_free(m);
+        }
+    }
+
+    #[test]
+    fn settings() {
+        unsafe {
+            let m = beebc_new();
+            // JN 2, on its own, is only defined with the signed flags.
+            let mut prog = [0u8; 16];
+            prog[0] = 0xC2;
+            beebc_load(m, prog.as_ptr(), prog.len());
+            beebc_run(m, 0);
+            assert_eq!(beebc_last_error(m), BEEBC_UNDEFINED_OPCODE);
+            beebc_reset(m);
+            beebc_set_nv_flags(m, true);
+            assert_eq!(beebc_set_pc_overflow(m, 2), BEEBC_OK);
+            beebc_run(m, 0);
+            assert_eq!(beebc_last_error(m), BEEBC_OK);
+            assert!(beebc_halted(m));
+            beebc_free(m);
+        }
+    }
+
+    #[test]
+    fn registers() {
+        unsafe {
+            let m = beebc_new();
+            (*m).ebc.reg_in = 9;
+            (*m).ebc.epc = 3;
+            (*m).ebc.ie = true;
+            let mut regs: BeebcRegisters = std::mem::zeroed();
+            beebc_registers(m, &mut regs);
+            assert_eq!((regs.reg_in, regs.epc, regs.ie, regs.irq), (9, 3, true, false));
+            beebc_free(m);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+	Error,
+	Warning,
+	Note, // More about the error or warning before it.
+}
+
+impl fmt::Display for Level {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Level::Error => write!(f, "error"),
+			Level::Warning => write!(f, "warning"),
+			Level::Note => write!(f, "note"),
+		}
+	}
+}
+
+// A stretch of one source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+	pub file: String,
+	pub line: usize, // From 1.
+	pub col:  usize, // From 1, in characters.
+	pub len:  usize, // In characters.
+	pub text: String, // The whole source line.
+}
+
+// An error or warning, pointing at the source it is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+	pub level:   Level,
+	pub message: String,
+	pub span:    Option<Span>,
+	pub notes:   Vec<Diagnostic>, // Printed after it, such as where a macro was called.
+}
+
+impl Diagnostic {
+	pub fn error(span: Option<Span>, message: String) -> Diagnostic {
+		Diagnostic { level: Level::Error, message, span, notes: Vec::new() }
+	}
+
+	pub fn warning(span: Option<Span>, message: String) -> Diagnostic {
+		Diagnostic { level: Level::Warning, message, span, notes: Vec::new() }
+	}
+
+	pub fn note(span: Option<Span>, message: String) -> Diagnostic {
+		Diagnostic { level: Level::Note, message, span, notes: Vec::new() }
+	}
+}
+
+// Prints as
+//
+//   prog.ebc:3:5: error: undefined label "LOOOP"
+//      3 | JMP LOOOP
+//        |     ^^^^^
+//
+// followed by any notes, in the same form.
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let span = match &self.span {
+			Some(span) => span,
+			None => {
+				writeln!(f, "{}: {}", self.level, self.message)?;
+				return self.notes.iter().try_for_each(|n| write!(f, "{}", n));
+			},
+		};
+		writeln!(f, "{}:{}:{}: {}: {}", span.file, span.line, span.col, self.level, self.message)?;
+		let gutter = span.line.to_string().len().max(4);
+		writeln!(f, "{:>w$} | {}", span.line, span.text, w = gutter)?;
+		// Tabs are copied, so the caret lines up however they are shown.
+		let pad: String = span.text.chars().take(span.col - 1)
+			.map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+		writeln!(f, "{:>w$} | {}{}", "", pad, "^".repeat(span.len.max(1)), w = gutter)?;
+		self.notes.iter().try_for_each(|n| write!(f, "{}", n))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn caret_under_the_span() {
+		let span = Span { file: "p.ebc".to_string(), line: 3, col: 5, len: 5, text: "JMP LOOOP".to_string() };
+		let d = Diagnostic::error(Some(span), "undefined label \"LOOOP\"".to_string());
+		assert_eq!(d.to_string(), "p.ebc:3:5: error: undefined label \"LOOOP\"\n   3 | JMP LOOOP\n     |     ^^^^^\n");
+	}
+
+	#[test]
+	fn tabs_are_kept() {
+		let span = Span { file: "p.ebc".to_string(), line: 1, col: 3, len: 1, text: "\t\tX".to_string() };
+		let d = Diagnostic::warning(Some(span), "w".to_string());
+		assert!(d.to_string().ends_with("   | \t\t^\n"));
+	}
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use super::{integer, is_label_name, is_op_code, Diagnostic, Line, Symbol, Word};
+
+// Binary operators, loosest binding first. Within a level they group to
+// the left, so 8-2-1 is 5. Comparisons give 1 if they hold, or 0.
+const LEVELS: [&[&str]; 7] = [
+	&["|"],
+	&["&"],
+	&["==", "!="],
+	&["<", "<=", ">", ">="],
+	&["<<", ">>"],
+	&["+", "-"],
+	&["*", "/"],
+];
+
+// What names and `.` stand for, where an expression is evaluated.
+pub(super) struct Scope<'a> {
+	pub symbols: &'a HashMap<String, Symbol>,
+	pub here:    i64,  // Address of the line the expression is on.
+	pub early:   bool, // First pass - only names defined further up are known.
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+	Atom, // A number, a character or a name.
+	Here, // .
+	Op,
+	Open,
+	Close,
+}
+
+struct Token<'a> {
+	kind: Kind,
+	text: &'a str,
+	col:  usize,
+}
+
+// Evaluates the expression `w`, such as `TABLE+1` or `(. + 2) & 0xF`.
+pub(super) fn eval(l: &Line, w: &Word, scope: &Scope) -> Result<i64, Box<Diagnostic>> {
+	let tokens = lex(l, w)?;
+	let end = w.col + w.text.len();
+	let mut p = Parser { l, tokens, pos: 0, end, scope };
+	let value = p.binary(0)?;
+	match p.tokens.get(p.pos) {
+		Some(t) => Err(Box::new(Diagnostic::error(l.span(t.col, t.text.len()), format!("expected an operator, found {:?}", t.text)))),
+		None => Ok(value),
+	}
+}
+
+fn is_atom_char(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '%'
+}
+
+fn lex<'a>(l: &Line, w: &Word<'a>) -> Result<Vec<Token<'a>>, Box<Diagnostic>> {
+	let mut tokens = Vec::new();
+	let mut i = 0;
+	while let Some(c) = w.text[i..].chars().next() {
+		let rest = &w.text[i..];
+		let (kind, len) = if c == ' ' || c == '\t' {
+			i += 1;
+			continue;
+		} else if c == '\'' {
+			// Up to the closing quote. Without one, the whole rest is
+			// taken, and the number reading says what is wrong.
+			let mut chars = rest.char_indices().skip(1);
+			let mut len = rest.len();
+			while let Some((j, c)) = chars.next() {
+				match c {
+					'\\' => { chars.next(); },
+					'\'' => {
+						len = j + 1;
+						break;
+					},
+					_ => {},
+				}
+			}
+			(Kind::Atom, len)
+		} else if ["<<", ">>", "<=", ">=", "==", "!="].iter().any(|op| rest.starts_with(op)) {
+			(Kind::Op, 2)
+		} else if "+-*/&|<>".contains(c) {
+			(Kind::Op, 1)
+		} else if c == '(' {
+			(Kind::Open, 1)
+		} else if c == ')' {
+			(Kind::Close, 1)
+		} else if is_atom_char(c) {
+			(Kind::Atom, rest.find(|c| !is_atom_char(c)).unwrap_or(rest.len()))
+		} else if c == '.' && !rest[1..].starts_with(is_atom_char) {
+			(Kind::Here, 1)
+		} else {
+			let msg = format!("unexpected {:?} in expression", c);
+			return Err(Box::new(Diagnostic::error(l.span(w.col + i, c.len_utf8()), msg)));
+		};
+		tokens.push(Token { kind, text: &rest[..len], col: w.col + i });
+		i += len;
+	}
+	Ok(tokens)
+}
+
+struct Parser<'a, 'b> {
+	l:      &'b Line,
+	tokens: Vec<Token<'a>>,
+	pos:    usize,
+	end:    usize, // Column just past the expression.
+	scope:  &'b Scope<'b>,
+}
+
+impl Parser<'_, '_> {
+	fn error(&self, t: Option<&Token>, msg: String) -> Box<Diagnostic> {
+		Box::new(match t {
+			Some(t) => Diagnostic::error(self.l.span(t.col, t.text.len()), msg),
+			None => Diagnostic::error(self.l.span(self.end, 1), msg),
+		})
+	}
+
+	fn binary(&mut self, level: usize) -> Result<i64, Box<Diagnostic>> {
+		if level == LEVELS.len() {
+			return self.unary();
+		}
+		let mut lhs = self.binary(level + 1)?;
+		while let Some(t) = self.tokens.get(self.pos) {
+			if t.kind != Kind::Op || !LEVELS[level].contains(&t.text) {
+				break;
+			}
+			let at = self.pos;
+			self.pos += 1;
+			let rhs = self.binary(level + 1)?;
+			let op = &self.tokens[at];
+			lhs = apply(op.text, lhs, rhs).map_err(|msg| self.error(Some(op), msg))?;
+		}
+		Ok(lhs)
+	}
+
+	fn unary(&mut self) -> Result<i64, Box<Diagnostic>> {
+		match self.tokens.get(self.pos) {
+			Some(t) if t.kind == Kind::Op && t.text == "-" => {
+				let at = self.pos;
+				self.pos += 1;
+				let v = self.unary()?;
+				v.checked_neg().ok_or_else(|| self.error(Some(&self.tokens[at]), "overflows".to_string()))
+			},
+			_ => self.primary(),
+		}
+	}
+
+	fn primary(&mut self) -> Result<i64, Box<Diagnostic>> {
+		let t = match self.tokens.get(self.pos) {
+			Some(t) => t,
+			None => return Err(self.error(None, "expected a value".to_string())),
+		};
+		self.pos += 1;
+		match t.kind {
+			Kind::Atom => self.atom(t),
+			Kind::Here => Ok(self.scope.here),
+			Kind::Open => {
+				let v = self.binary(0)?;
+				match self.tokens.get(self.pos) {
+					Some(t) if t.kind == Kind::Close => {
+						self.pos += 1;
+						Ok(v)
+					},
+					t => Err(self.error(t, "expected ')'".to_string())),
+				}
+			},
+			_ => Err(self.error(Some(t), format!("expected a value, found {:?}", t.text))),
+		}
+	}
+
+	fn atom(&self, t: &Token) -> Result<i64, Box<Diagnostic>> {
+		if let Some(v) = integer(t.text) {
+			return v.map_err(|msg| self.error(Some(t), msg));
+		}
+		if is_op_code(t.text) {
+			return Err(self.error(Some(t), format!("{:?} is an instruction, not a value", t.text)));
+		}
+		if !is_label_name(t.text) {
+			return Err(self.error(Some(t), format!("{:?} is not a number or a name", t.text)));
+		}
+		match self.scope.symbols.get(t.text) {
+			Some(sym) => Ok(sym.value),
+			None if self.scope.early => Err(self.error(Some(t), format!("{:?} must be defined before it is used here", t.text))),
+			None => Err(self.error(Some(t), format!("undefined label {:?}", t.text))),
+		}
+	}
+}
+
+fn apply(op: &str, a: i64, b: i64) -> Result<i64, String> {
+	let v = match op {
+		"+" => a.checked_add(b),
+		"-" => a.checked_sub(b),
+		"*" => a.checked_mul(b),
+		"/" if b == 0 => return Err("division by zero".to_string()),
+		"/" => a.checked_div(b),
+		"&" => Some(a & b),
+		"|" => Some(a | b),
+		"<<" | ">>" if !(0..63).contains(&b) => return Err(format!("cannot shift by {}", b)),
+		"<<" => a.checked_mul(1 << b),
+		">>" => Some(a >> b),
+		"==" => Some((a == b) as i64),
+		"!=" => Some((a != b) as i64),
+		"<" => Some((a < b) as i64),
+		"<=" => Some((a <= b) as i64),
+		">" => Some((a > b) as i64),
+		">=" => Some((a >= b) as i64),
+		_ => None,
+	};
+	v.ok_or_else(|| "overflows".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::beebc::asm::tests::line;
+
+	fn symbols() -> HashMap<String, Symbol> {
+		let mut symbols = HashMap::new();
+		symbols.insert("TEN".to_string(), Symbol { value: 10, at: None, constant: true });
+		symbols.insert("LOOP".to_string(), Symbol { value: 3, at: None, constant: false });
+		symbols
+	}
+
+	fn eval_at(text: &str, here: i64, early: bool) -> Result<i64, String> {
+		let l = line(text);
+		let w = Word { text: &l.text, col: 0 };
+		let symbols = symbols();
+		eval(&l, &w, &Scope { symbols: &symbols, here, early }).map_err(|d| d.message)
+	}
+
+	fn value(text: &str) -> i64 {
+		eval_at(text, 0, false).unwrap()
+	}
+
+	#[test]
+	fn precedence() {
+		assert_eq!(value("1+2*3"), 7);
+		assert_eq!(value("(1+2)*3"), 9);
+		assert_eq!(value("8-2-1"), 5);
+		assert_eq!(value("16/4/2"), 2);
+		assert_eq!(value("1|2&3"), 3);
+		assert_eq!(value("6&3|8"), 10);
+		assert_eq!(value("1<<2+1"), 8);
+		assert_eq!(value("2+3==5"), 1);
+		assert_eq!(value("1<2==1"), 1);
+		assert_eq!(value("4>>1 > 1"), 1);
+		assert_eq!(value("5 & 4 == 4"), 1);
+		assert_eq!(value("-2*3"), -6);
+		assert_eq!(value("--2"), 2);
+		assert_eq!(value("-(1+2)"), -3);
+	}
+
+	#[test]
+	fn arithmetic() {
+		assert_eq!(value("10/3"), 3);
+		assert_eq!(value("-7/2"), -3);
+		assert_eq!(value("1<<62"), 1 << 62);
+		assert_eq!(value("-8>>1"), -4);
+		assert_eq!(value("3 != 3"), 0);
+		assert_eq!(value("3 <= 3"), 1);
+		assert_eq!(value("3 >= 4"), 0);
+	}
+
+	#[test]
+	fn names_and_here() {
+		assert_eq!(value("TEN+1"), 11);
+		assert_eq!(value("LOOP * 2"), 6);
+		assert_eq!(value("'A'+1"), 66);
+		assert_eq!(value("$F & %1010"), 10);
+		assert_eq!(eval_at(". + 2", 4, false), Ok(6));
+		assert_eq!(eval_at("(.+2)&$F", 15, false), Ok(1));
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(eval_at("1/0", 0, false), Err("division by zero".to_string()));
+		assert_eq!(eval_at("1<<63", 0, false), Err("cannot shift by 63".to_string()));
+		assert_eq!(eval_at("1>>-1", 0, false), Err("cannot shift by -1".to_string()));
+		assert_eq!(eval_at("2<<62", 0, false), Err("overflows".to_string()));
+		assert_eq!(eval_at("1 +", 0, false), Err("expected a value".to_string()));
+		assert_eq!(eval_at("(1", 0, false), Err("expected ')'".to_string()));
+		assert_eq!(eval_at("1 2", 0, false), Err("expected an operator, found \"2\"".to_string()));
+		assert_eq!(eval_at("1 # 2", 0, false), Err("unexpected '#' in expression".to_string()));
+		assert_eq!(eval_at("LDA+1", 0, false), Err("\"LDA\" is an instruction, not a value".to_string()));
+		assert_eq!(eval_at("NOWHERE", 0, false), Err("undefined label \"NOWHERE\"".to_string()));
+		assert_eq!(eval_at("NOWHERE", 0, true), Err("\"NOWHERE\" must be defined before it is used here".to_string()));
+	}
+
+	#[test]
+	fn errors_point_at_the_token() {
+		let l = line("LDA 1 + /");
+		let w = Word { text: &l.text[4..], col: 4 };
+		let symbols = symbols();
+		let d = eval(&l, &w, &Scope { symbols: &symbols, here: 0, early: false }).unwrap_err();
+		let span = d.span.unwrap();
+		assert_eq!((span.col, span.len), (9, 1));
+	}
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::beebc::opcode::{Instruction, Opcode, FETCH_STEPS};
+use super::{Role, Stmt, Symbol};
+
+// Lays out what each line assembled to,
+//
+//   addr  binary    hex  cycles    line  source
+//   0000  00011110  1E   8 (4)        1  LOOP: LDA COUNT
+//
+// one row per byte, then a table of every label and constant. Cycles are
+// the clock ticks an instruction takes, and in brackets how many of them
+// do anything - for conditional jumps, if taken and if not. Every source
+// line is shown, directives and macro definitions too. The lines a macro
+// call writes follow it, with the call's line number and a + after it.
+// Lines left out by an .if have a - after their line number.
+pub(super) fn listing(file_name: &str, stmts: &[Stmt], symbol_table: &HashMap<String, Symbol>) -> String {
+	let mut text = String::new();
+	let _ = writeln!(text, "; {}", file_name);
+	let _ = writeln!(text, "; cycles: clock ticks taken (ticks doing anything, if taken/not taken)");
+	let _ = writeln!(text, "addr  binary    hex  cycles    line  source");
+	let mut file = file_name;
+	let mut site = (file_name, 0); // Where the lines being listed are in the source.
+	for st in stmts {
+		let l = st.line;
+		if !l.expanded {
+			site = (&l.file, l.number);
+		}
+		if site.0 != file {
+			let _ = writeln!(text, "; {}", site.0);
+			file = site.0;
+		}
+		let addr = if st.label.is_some() || !st.bytes.is_empty() { format!("{:04b}", st.addr) } else { String::new() };
+		let (bin, hex) = match st.bytes.first() {
+			Some(b) => (format!("{:08b}", b), format!("{:02X}", b)),
+			None => (String::new(), String::new()),
+		};
+		let cycles = instruction(st).map_or(String::new(), cycles);
+		let mark = match l.role {
+			Role::Skipped => '-',
+			_ if l.expanded => '+',
+			_ => ' ',
+		};
+		let row = format!("{:<4}  {:<8}  {:<3}  {:<8}  {:>4}{} {}", addr, bin, hex, cycles, site.1, mark, l.text);
+		let _ = writeln!(text, "{}", row.trim_end());
+		for (i, b) in st.bytes.iter().enumerate().skip(1) {
+			let _ = writeln!(text, "{:04b}  {:08b}  {:02X}", st.addr + i, b, b);
+		}
+	}
+
+	let mut names: Vec<&String> = symbol_table.keys().collect();
+	names.sort();
+	let w = names.iter().map(|n| n.len()).max().unwrap_or(0).max(4);
+	let _ = writeln!(text);
+	let _ = writeln!(text, "; symbols");
+	let _ = writeln!(text, "{:<w$}  {:>6}  hex  binary    defined", "name", "value", w = w);
+	for name in names {
+		let sym = &symbol_table[name];
+		let (hex, bin) = if (-128..=255).contains(&sym.value) {
+			(format!("{:02X}", sym.value as u8), format!("{:08b}", sym.value as u8))
+		} else {
+			(String::new(), String::new())
+		};
+		let kind = if sym.constant { "constant" } else { "label" };
+		let at = match &sym.at {
+			Some((file, line)) => format!("{}:{}", file, line),
+			None => "-D".to_string(),
+		};
+		let _ = writeln!(text, "{:<w$}  {:>6}  {:<3}  {:<8}  {}, {}", name, sym.value, hex, bin, kind, at, w = w);
+	}
+	text
+}
+
+fn instruction(st: &Stmt) -> Option<&'static Instruction> {
+	st.words.first()?.text.parse::<Opcode>().ok().map(Opcode::info)
+}
+
+fn cycles(i: &Instruction) -> String {
+	if i.flag != 0 {
+		format!("{} ({}/{})", i.cycles(), i.busy(), FETCH_STEPS)
+	} else {
+		format!("{} ({})", i.cycles(), i.busy())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::beebc::asm::{assemble, Options};
+	use crate::beebc::asm::tests::temp_file;
+
+	#[test]
+	fn lists_every_line() {
+		let src = temp_file("listed.ebc", ".macro TWO\n\tOUT\n\tOUT\n.endm\n.if 0\n\tNOP\n.endif\nSTART: TWO\n\tJC START\n");
+		let list = format!("{}.lst", src);
+		let options = Options { listing: Some(list.clone()), ..Options::default() };
+		assert!(assemble(&src, &format!("{}.bin", src), &options).is_empty());
+		let text = std::fs::read_to_string(list).unwrap();
+		let rows: Vec<&str> = text.lines().skip(3).take(12).map(|r| r.trim_end()).collect();
+		assert_eq!(rows, [
+			"                                  1  .macro TWO",
+			"                                  2  \tOUT",
+			"                                  3  \tOUT",
+			"                                  4  .endm",
+			"                                  5  .if 0",
+			"                                  6- \tNOP",
+			"                                  7  .endif",
+			"0000                              8  START: TWO",
+			"0000  11100000  E0   8 (3)        8+ \tOUT",
+			"0001  11100000  E0   8 (3)        8+ \tOUT",
+			"0010  01110000  70   8 (3/2)      9  \tJC START",
+			"",
+		]);
+		assert!(text.contains("START       0  00   00000000  label,"));
+	}
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use super::{args, is_label_name, statement, words, Diagnostic, Line, Role, Span, Word};
+
+// How deep macros can call other macros, so one that calls itself stops.
+const MAX_DEPTH: usize = 16;
+
+struct Macro {
+	params:  Vec<String>,
+	locals:  Vec<String>, // Labels defined in the body.
+	body:    Vec<Line>,
+	defined: Diagnostic,  // Note pointing at the .macro line.
+}
+
+// The first word of a line, if it is the directive `name` and the line
+// is to be assembled.
+fn is_directive(l: &Line, name: &str) -> bool {
+	l.role == Role::Code && words(&l.text).first().is_some_and(|w| w.text.eq_ignore_ascii_case(name))
+}
+
+// The line, kept only to be listed.
+fn listed(l: &Line) -> Line {
+	let role = if l.role == Role::Skipped { Role::Skipped } else { Role::Listed };
+	Line { role, ..l.clone() }
+}
+
+// Expands macros, giving the lines the two passes see. Definitions,
+//
+//   .macro INC addr
+//   	LDA addr
+//   	ADD ONE
+//   	STA addr
+//   .endm
+//
+// are only listed, and calls, `INC COUNT`, followed by the body with each
+// parameter swapped for what was passed. Labels defined in the body are
+// renamed for every call, so a macro can be called more than once. Each
+// line from a macro notes where it was called from.
+pub(super) fn expand(source: Vec<Line>, diags: &mut Vec<Diagnostic>) -> Vec<Line> {
+	let mut macros = HashMap::new();
+	let mut out = Vec::new();
+	let mut calls = 0;
+	let mut renamed = HashMap::new(); // Note pointing at the call, by the name a label was given.
+	let mut i = 0;
+	while i < source.len() {
+		let l = &source[i];
+		i += 1;
+		if is_directive(l, ".endm") {
+			let w = &words(&l.text)[0];
+			diags.push(Diagnostic::error(l.at(w), ".endm without a .macro".to_string()));
+			out.push(listed(l));
+			continue;
+		}
+		if !is_directive(l, ".macro") {
+			call(l, &macros, 0, &mut calls, &mut renamed, &mut out, diags);
+			continue;
+		}
+		let end = source[i..].iter().position(|b| is_directive(b, ".endm")).map(|p| i + p);
+		let body = &source[i..end.unwrap_or(source.len())];
+		let ws = words(&l.text);
+		if end.is_none() {
+			diags.push(Diagnostic::error(l.at(&ws[0]), ".macro without an .endm".to_string()));
+		}
+		for b in body.iter().filter(|b| is_directive(b, ".macro")) {
+			let w = &words(&b.text)[0];
+			diags.push(Diagnostic::error(b.at(w), "macros cannot be defined inside a macro".to_string()));
+		}
+		let whole = &source[i - 1..(i + body.len() + 1).min(source.len())];
+		out.extend(whole.iter().map(listed));
+		i += body.len() + 1;
+		define(l, &ws, body, &mut macros, diags);
+	}
+	// The source itself must not use a name given to a renamed label.
+	for l in source.iter().filter(|l| l.role == Role::Code) {
+		for (start, end) in names(&l.text) {
+			let name = &l.text[start..end];
+			if let Some(note) = renamed.get(name) {
+				let mut d = Diagnostic::error(l.span(start, end - start), format!("{:?} is also the name a macro gives one of its labels", name));
+				d.notes.push(note.clone());
+				diags.push(d);
+			}
+		}
+	}
+	out
+}
+
+fn define(l: &Line, ws: &[Word], body: &[Line], macros: &mut HashMap<String, Macro>, diags: &mut Vec<Diagnostic>) {
+	let name = match ws.get(1) {
+		Some(name) => name,
+		None => {
+			diags.push(Diagnostic::error(l.after(&ws[0]), ".macro needs a name".to_string()));
+			return;
+		},
+	};
+	if !is_label_name(name.text) {
+		let msg = format!("{:?} cannot be a macro name - names are a letter or _, then letters, digits and _, and not an instruction", name.text);
+		diags.push(Diagnostic::error(l.at(name), msg));
+		return;
+	}
+	if let Some(prev) = macros.get(name.text) {
+		let mut d = Diagnostic::error(l.at(name), format!("macro {:?} is already defined", name.text));
+		d.notes.push(prev.defined.clone());
+		diags.push(d);
+		return;
+	}
+	let mut params: Vec<String> = Vec::new();
+	for p in args(l, &ws[2..]) {
+		if !is_label_name(p.text) {
+			diags.push(Diagnostic::error(l.span(p.col, p.text.len().max(1)), format!("{:?} cannot be a parameter name", p.text)));
+		} else if params.iter().any(|q| q == p.text) {
+			diags.push(Diagnostic::error(l.at(&p), format!("parameter {:?} is already used", p.text)));
+		} else {
+			params.push(p.text.to_string());
+		}
+	}
+	// Only labels written with a colon - a bare word may be a macro call.
+	// Problems with the labels themselves are reported where they are used.
+	let body: Vec<Line> = body.iter().filter(|b| b.role == Role::Code).cloned().collect();
+	let locals = body.iter()
+		.filter(|b| words(&b.text).first().is_some_and(|w| w.text.contains(':')))
+		.filter_map(|b| statement(b, false, &mut Vec::new()).label.map(|w| w.text.to_string()))
+		.collect();
+	let defined = Diagnostic::note(l.at(name), format!("macro {} is defined here", name.text));
+	macros.insert(name.text.to_string(), Macro { params, locals, body, defined });
+}
+
+// Adds a line to `out`, followed by its expansion if it calls a macro.
+fn call(l: &Line, macros: &HashMap<String, Macro>, depth: usize, calls: &mut usize,
+	renamed: &mut HashMap<String, Diagnostic>, out: &mut Vec<Line>, diags: &mut Vec<Diagnostic>) {
+	if l.role != Role::Code {
+		out.push(l.clone());
+		return;
+	}
+	// A label, if any, then the name - split as in statement.
+	let mut ws = words(&l.text);
+	let mut label_end = None;
+	if let Some(colon) = ws.first().and_then(|w| w.text.find(':')) {
+		let first = ws.remove(0);
+		let rest = &first.text[colon + 1..];
+		if !rest.is_empty() {
+			ws.insert(0, Word { text: rest, col: first.col + colon + 1 });
+		}
+		label_end = Some(first.col + colon + 1);
+	}
+	let (name, m) = match ws.first().and_then(|w| macros.get(w.text).map(|m| (w, m))) {
+		Some(found) => found,
+		None => {
+			out.push(l.clone());
+			return;
+		},
+	};
+	out.push(Line { role: Role::Call(label_end.unwrap_or(0)), ..l.clone() });
+	let mut error = |msg: String, at: Option<Span>, defined: bool| {
+		let mut d = Diagnostic::error(at, msg);
+		if defined {
+			d.notes.push(m.defined.clone());
+		}
+		d.notes.extend(l.calls.iter().cloned());
+		diags.push(d);
+	};
+	let given = args(l, &ws[1..]);
+	if given.len() != m.params.len() {
+		let s = if m.params.len() == 1 { "" } else { "s" };
+		error(format!("{} takes {} argument{}, given {}", name.text, m.params.len(), s, given.len()), l.at(name), true);
+		return;
+	}
+	if let Some(missing) = given.iter().find(|a| a.text.is_empty()) {
+		error("missing argument".to_string(), l.span(missing.col, 1), false);
+		return;
+	}
+	if depth == MAX_DEPTH {
+		// The whole chain of calls would be as deep, so only where it started.
+		let mut d = Diagnostic::error(l.at(name), format!("macros are nested more than {} deep - does {} call itself?", MAX_DEPTH, name.text));
+		d.notes.push(m.defined.clone());
+		d.notes.extend(l.calls.last().cloned());
+		diags.push(d);
+		return;
+	}
+
+	*calls += 1;
+	let mut swap: HashMap<&str, String> = HashMap::new();
+	for (p, a) in m.params.iter().zip(given.iter()) {
+		swap.insert(p, a.text.to_string());
+	}
+	for local in m.locals.iter() {
+		let new = format!("{}__{}", local, calls);
+		let msg = format!("macro {} renames its label {} to {} for this call", name.text, local, new);
+		renamed.insert(new.clone(), Diagnostic::note(l.at(name), msg));
+		swap.insert(local, new);
+	}
+	let mut notes = vec![Diagnostic::note(l.at(name), format!("in macro {}, called from here", name.text))];
+	notes.extend(l.calls.iter().cloned());
+	for b in m.body.iter() {
+		let line = Line { text: substitute(&b.text, &swap), calls: notes.clone(), expanded: true, ..b.clone() };
+		call(&line, macros, depth + 1, calls, renamed, out, diags);
+	}
+}
+
+// Swaps whole names in `text`, leaving character literals and comments.
+fn substitute(text: &str, swap: &HashMap<&str, String>) -> String {
+	let mut out = String::new();
+	let mut last = 0;
+	for (start, end) in names(text) {
+		if let Some(new) = swap.get(&text[start..end]) {
+			out.push_str(&text[last..start]);
+			out.push_str(new);
+			last = end;
+		}
+	}
+	out.push_str(&text[last..]);
+	out
+}
+
+// Where the names in `text` are, outside character literals and comments.
+fn names(text: &str) -> Vec<(usize, usize)> {
+	let mut found = Vec::new();
+	let mut chars = text.char_indices().peekable();
+	while let Some((i, c)) = chars.next() {
+		if c == ';' || (c == '/' && chars.peek().is_some_and(|(_, n)| *n == '/')) {
+			break;
+		}
+		if c == '\'' {
+			while let Some((_, c)) = chars.next() {
+				match c {
+					'\\' => { chars.next(); },
+					'\'' => break,
+					_ => {},
+				}
+			}
+		} else if c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '%' {
+			// Numbers too, so the FF in $FF is not taken for a name.
+			let mut end = i + c.len_utf8();
+			while let Some((j, n)) = chars.peek() {
+				if !(n.is_ascii_alphanumeric() || *n == '_') {
+					break;
+				}
+				end = j + n.len_utf8();
+				chars.next();
+			}
+			found.push((i, end));
+		}
+	}
+	found
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::beebc::asm::tests::line;
+
+	fn source(text: &str) -> Vec<Line> {
+		text.lines().enumerate().map(|(i, t)| Line { number: i + 1, ..line(t) }).collect()
+	}
+
+	// The lines to be assembled, after expanding.
+	fn code(text: &str) -> (Vec<String>, Vec<Diagnostic>) {
+		let mut diags = Vec::new();
+		let out = expand(source(text), &mut diags);
+		let code = out.iter()
+			.filter(|l| l.role == Role::Code)
+			.map(|l| l.text.trim().to_string())
+			.collect();
+		(code, diags)
+	}
+
+	#[test]
+	fn substitutes_whole_names() {
+		let mut swap = HashMap::new();
+		swap.insert("cell", "COUNT".to_string());
+		swap.insert("F", "X".to_string());
+		assert_eq!(substitute("\tLDA cell", &swap), "\tLDA COUNT");
+		assert_eq!(substitute("LDA cellar+cell", &swap), "LDA cellar+COUNT");
+		assert_eq!(substitute("LDI $F", &swap), "LDI $F");
+		assert_eq!(substitute("LDI 'F' ; F cell", &swap), "LDI 'F' ; F cell");
+		assert_eq!(substitute("LDI '\\'' + F // cell", &swap), "LDI '\\'' + X // cell");
+	}
+
+	#[test]
+	fn expands_calls() {
+		let (code, diags) = code(".macro INC addr, by\n\tLDA addr\n\tADD by\n\tSTA addr\n.endm\nINC COUNT, ONE\nHLT");
+		assert!(diags.is_empty());
+		assert_eq!(code, ["LDA COUNT", "ADD ONE", "STA COUNT", "HLT"]);
+	}
+
+	#[test]
+	fn renames_labels_for_each_call() {
+		let text = ".macro WAIT\nLOOP:\tJZ LOOP\n\tJMP OUT\n.endm\nWAIT\nWAIT\nOUT: HLT";
+		let (code, diags) = code(text);
+		assert!(diags.is_empty());
+		assert_eq!(code, ["LOOP__1:\tJZ LOOP__1", "JMP OUT", "LOOP__2:\tJZ LOOP__2", "JMP OUT", "OUT: HLT"]);
+	}
+
+	#[test]
+	fn renamed_labels_cannot_clash() {
+		let text = ".macro WAIT\nLOOP:\tJZ LOOP\n.endm\nWAIT\nLOOP__1: JMP LOOP__1 ; LOOP__1\n";
+		let (_, diags) = code(text);
+		let spans: Vec<(usize, usize)> = diags.iter().map(|d| d.span.as_ref().map_or((0, 0), |s| (s.line, s.col))).collect();
+		assert_eq!(spans, [(5, 1), (5, 14)]);
+		assert_eq!(diags[0].message, "\"LOOP__1\" is also the name a macro gives one of its labels");
+		assert_eq!(diags[0].notes[0].span.as_ref().map(|s| s.line), Some(4));
+		// Other names, in a macro or out, are fine.
+		let (_, diags) = code(".macro WAIT\nLOOP:\tJZ LOOP\n.endm\nWAIT\nLOOP__2: JMP LOOP\n");
+		assert!(diags.is_empty());
+	}
+
+	#[test]
+	fn keeps_the_call_and_its_label() {
+		let mut diags = Vec::new();
+		let out = expand(source(".macro TWO\n\tOUT\n\tOUT\n.endm\nSTART: TWO"), &mut diags);
+		let call = out.iter().find(|l| matches!(l.role, Role::Call(_))).unwrap();
+		assert_eq!((call.number, call.role), (5, Role::Call(6)));
+		let expanded: Vec<usize> = out.iter().filter(|l| l.expanded).map(|l| l.number).collect();
+		assert_eq!(expanded, [2, 3]);
+		assert_eq!(out.iter().filter(|l| l.role == Role::Listed).count(), 4);
+	}
+
+	#[test]
+	fn nested_calls() {
+		let (code, diags) = code(".macro ONE x\n\tLDA x\n.endm\n.macro BOTH x, y\n\tONE x\n\tONE y\n.endm\nBOTH A, B");
+		assert!(diags.is_empty());
+		assert_eq!(code, ["LDA A", "LDA B"]);
+	}
+
+	#[test]
+	fn errors() {
+		let messages = |text: &str| code(text).1.into_iter().map(|d| d.message).collect::<Vec<_>>();
+		assert_eq!(messages(".macro M a\n.endm\nM"), ["M takes 1 argument, given 0"]);
+		assert_eq!(messages(".macro M a, b\n.endm\nM 1,"), ["missing argument"]);
+		assert_eq!(messages(".macro M\n.endm\n.macro M\n.endm"), ["macro \"M\" is already defined"]);
+		assert_eq!(messages(".endm"), [".endm without a .macro"]);
+		assert_eq!(messages(".macro M"), [".macro without an .endm"]);
+		assert_eq!(messages(".macro M a, a\n.endm"), ["parameter \"a\" is already used"]);
+		assert!(messages(".macro LDA\n.endm")[0].starts_with("\"LDA\" cannot be a macro name"));
+		let deep = messages(".macro R\n\tR\n.endm\nR");
+		assert_eq!(deep, ["macros are nested more than 16 deep - does R call itself?"]);
+	}
+
+	#[test]
+	fn skipped_lines_do_not_end_a_macro() {
+		let mut lines = source(".macro M\n.endm\n\tOUT\n.endm\nM");
+		lines[1].role = Role::Skipped;
+		let mut diags = Vec::new();
+		let out = expand(lines, &mut diags);
+		assert!(diags.is_empty());
+		let code: Vec<&str> = out.iter().filter(|l| l.role == Role::Code).map(|l| l.text.trim()).collect();
+		assert_eq!(code, ["OUT"]);
+	}
+}
@@ -0,0 +1,816 @@
+use std::fs;
+use std::collections::HashMap;
+use crate::beebc::opcode::Opcode;
+
+mod diag;
+mod expr;
+mod listing;
+mod macros;
+mod source;
+pub use diag::{Diagnostic, Level, Span};
+use expr::Scope;
+
+// Largest operand an instruction can hold - the low 4 bits of its byte,
+// which is also the last address in ram.
+const OPERAND_MAX: u8 = 0b1111;
+
+// Bytes of ram, and so of the image written.
+const RAM_SIZE: usize = 16;
+
+// How a line takes part in assembling. Lines that are dealt with while
+// reading files or expanding macros are kept, so the listing shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+	Code,        // Assembled.
+	Call(usize), // A macro call. Only a label in front of it, the text up to this column, is assembled.
+	Listed,      // Used up already - .include, .if and the like, and macro definitions.
+	Skipped,     // In a branch of an .if that is not taken.
+}
+
+// One line of source, and where it came from.
+#[derive(Clone)]
+struct Line {
+	file:     String,
+	number:   usize,
+	text:     String,
+	calls:    Vec<Diagnostic>, // Notes on the includes and macro calls it came from, innermost first.
+	expanded: bool,            // Written by a macro, rather than in the source.
+	role:     Role,
+}
+
+impl Line {
+	// The part of this line from byte `col` (from 0), `len` long.
+	// `col` and `len` are in bytes, as words are found. Spans count
+	// characters, so the caret lines up under text like LÖÖP.
+	fn span(&self, col: usize, len: usize) -> Option<Span> {
+		let chars = |to: usize| match self.text.get(..to.min(self.text.len())) {
+			Some(s) => s.chars().count() + to.saturating_sub(self.text.len()),
+			None => to,
+		};
+		let (from, to) = (chars(col), chars(col + len));
+		Some(Span { file: self.file.clone(), line: self.number, col: from + 1, len: to.saturating_sub(from), text: self.text.clone() })
+	}
+
+	fn at(&self, word: &Word) -> Option<Span> {
+		self.span(word.col, word.text.len())
+	}
+
+	// Just past `word`, for things missing after it.
+	fn after(&self, word: &Word) -> Option<Span> {
+		self.span(word.col + word.text.len(), 1)
+	}
+}
+
+// A token, and the column it starts at.
+struct Word<'a> {
+	text: &'a str,
+	col:  usize,
+}
+
+// Splits a line into words, on spaces and tabs, up to any comment. Comments
+// start with // or ; and run to the end of the line. Character literals
+// are kept whole, so ' ' and ';' are words, not a gap or a comment.
+fn words(text: &str) -> Vec<Word<'_>> {
+	let mut words = Vec::new();
+	let mut start = None;
+	let mut quoted = false;
+	let mut chars = text.char_indices().peekable();
+	let mut end = text.len();
+	while let Some((i, c)) = chars.next() {
+		if quoted {
+			match c {
+				'\\' => { chars.next(); },
+				'\'' => quoted = false,
+				_ => {},
+			}
+			continue;
+		}
+		let comment = c == ';' || (c == '/' && chars.peek().is_some_and(|(_, n)| *n == '/'));
+		if comment {
+			end = i;
+			break;
+		}
+		if c == ' ' || c == '\t' {
+			if let Some(s) = start.take() {
+				words.push(Word { text: &text[s..i], col: s });
+			}
+		} else {
+			start.get_or_insert(i);
+			quoted = c == '\'';
+		}
+	}
+	if let Some(s) = start {
+		words.push(Word { text: &text[s..end], col: s });
+	}
+	words
+}
+
+// A line split into its label, if it has one, and the rest.
+struct Stmt<'a> {
+	line:  &'a Line,
+	label: Option<Word<'a>>,
+	words: Vec<Word<'a>>,
+	addr:  usize, // Where its bytes go, and how many there are - from the first pass.
+	size:  usize,
+	bytes: Vec<u8>, // What they are - from the second.
+}
+
+// What a label or constant stands for, and where it was defined.
+struct Symbol {
+	value:    i64,
+	at:       Option<(String, usize)>, // File and line, or None if given on the command line.
+	constant: bool, // Set by .equ, rather than an address.
+}
+
+// Assembler directives. These start with a dot, and are not instructions.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Directive {
+	Org,  // .org ADDR - carry on from ADDR.
+	Byte, // .byte V, V, ... (or .db) - a data byte for each value.
+	Fill, // .fill COUNT[, V] - COUNT bytes of V, or 0.
+	Equ,  // .equ NAME, V - names a constant.
+}
+
+fn directive(v: &str) -> Option<Directive> {
+	match v.to_ascii_lowercase().as_str() {
+		".org" => Some(Directive::Org),
+		".byte" | ".db" => Some(Directive::Byte),
+		".fill" => Some(Directive::Fill),
+		".equ" => Some(Directive::Equ),
+		_ => None,
+	}
+}
+
+// The comma separated arguments of a directive, given the words after it.
+// Commas inside character literals do not count. An argument left out,
+// as in `1,,2`, is kept as an empty word where it should have been.
+fn args<'a>(l: &'a Line, words: &[Word<'a>]) -> Vec<Word<'a>> {
+	let whole = match joined(l, words) {
+		Some(whole) => whole,
+		None => return Vec::new(),
+	};
+	let text = whole.text;
+	let mut args = Vec::new();
+	let mut start = 0;
+	let mut quoted = false;
+	let mut chars = text.char_indices();
+	while let Some((i, c)) = chars.next() {
+		if quoted {
+			match c {
+				'\\' => { chars.next(); },
+				'\'' => quoted = false,
+				_ => {},
+			}
+			continue;
+		}
+		match c {
+			'\'' => quoted = true,
+			',' => {
+				args.push(arg(text, start, i, whole.col));
+				start = i + 1;
+			},
+			_ => {},
+		}
+	}
+	args.push(arg(text, start, text.len(), whole.col));
+	args
+}
+
+// Words run back together, spaces and all, as one - an expression such
+// as `TABLE + 1`.
+fn joined<'a>(l: &'a Line, words: &[Word<'a>]) -> Option<Word<'a>> {
+	let (first, last) = (words.first()?, words.last()?);
+	Some(Word { text: &l.text[first.col..last.col + last.text.len()], col: first.col })
+}
+
+fn arg(text: &str, start: usize, end: usize, col: usize) -> Word<'_> {
+	let raw = &text[start..end];
+	let lead = raw.len() - raw.trim_start().len();
+	Word { text: raw.trim(), col: col + start + lead }
+}
+
+// Splits a line into a statement. Labels end with a colon, `LOOP:`, and can
+// have an instruction after them on the same line. A word alone on a line
+// that is not an instruction is an error, as it is most likely a misspelled
+// one - unless `legacy`, where it is taken as a label, as older sources
+// wrote them, with a warning. Either way it goes on as a label, so uses of
+// it are not reported too.
+fn statement<'a>(l: &'a Line, legacy: bool, diags: &mut Vec<Diagnostic>) -> Stmt<'a> {
+	let code = match l.role {
+		Role::Code => &l.text[..],
+		Role::Call(end) => &l.text[..end],
+		Role::Listed | Role::Skipped => "",
+	};
+	let mut words = words(code);
+	let mut label = None;
+	if let Some(colon) = words.first().and_then(|w| w.text.find(':')) {
+		let first = words.remove(0);
+		let rest = &first.text[colon + 1..];
+		if !rest.is_empty() {
+			words.insert(0, Word { text: rest, col: first.col + colon + 1 });
+		}
+		let name = Word { text: &first.text[..colon], col: first.col };
+		if is_label_name(name.text) {
+			label = Some(name);
+		} else {
+			let msg = format!("{:?} cannot be a label - labels are a letter or _, then letters, digits and _, and not an instruction", name.text);
+			diags.push(Diagnostic::error(l.span(first.col, colon + 1), msg));
+		}
+	} else if words.len() == 1 && is_symbol(words[0].text) {
+		let name = words.remove(0);
+		if legacy {
+			let msg = format!("{:?} is not an instruction, so it is taken as a label - labels should end with ':'", name.text);
+			diags.push(Diagnostic::warning(l.at(&name), msg));
+		} else {
+			let msg = format!("{:?} is not an instruction - a label needs a ':' after it", name.text);
+			diags.push(Diagnostic::error(l.at(&name), msg));
+		}
+		label = Some(name);
+	}
+	Stmt { line: l, label, words, addr: 0, size: 0, bytes: Vec::new() }
+}
+
+fn is_label_name(v: &str) -> bool {
+	let mut chars = v.chars();
+	let first = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+	first && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !is_op_code(v)
+}
+
+// Settings for assemble, from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+	pub include_dirs:  Vec<String>,        // Searched for .include files, after the including file's own directory.
+	pub defines:       Vec<(String, i64)>, // Constants, as if defined by .equ before the first line.
+	pub listing:       Option<String>,     // Where to write a listing, if anywhere.
+	pub legacy_labels: bool,               // Takes a bare word on a line of its own as a label.
+	pub ram_size:      Option<usize>,      // Bytes of ram the program may use, from address 0, if not all of it.
+	pub used:          bool,               // Also writes the addresses the program fills in, to the output name plus .used.
+}
+
+impl Options {
+	// Adds a constant from `NAME=VALUE`, or just `NAME`, which is 1.
+	pub fn define(&mut self, spec: &str) -> Result<(), String> {
+		let (name, value) = match spec.split_once('=') {
+			Some((name, value)) => match integer(value) {
+				Some(Ok(v)) => (name, v),
+				Some(Err(msg)) => return Err(msg),
+				None => return Err(format!("{:?} is not a number", value)),
+			},
+			None => (spec, 1),
+		};
+		if !is_label_name(name) {
+			return Err(format!("{:?} cannot be a constant name - names are a letter or _, then letters, digits and _, and not an instruction", name));
+		}
+		self.defines.retain(|(n, _)| n != name);
+		self.defines.push((name.to_string(), value));
+		Ok(())
+	}
+}
+
+// Assembles `file_name` into a 16 byte image at `out_name`. Returns every
+// error and warning found. The image is only written if there were no errors.
+pub fn assemble(file_name: &str, out_name: &str, options: &Options) -> Vec<Diagnostic> {
+	let mut diags = Vec::new();
+	let ram = options.ram_size.unwrap_or(RAM_SIZE).min(RAM_SIZE);
+	let lines = source::load(file_name, options, &mut diags);
+	let lines = macros::expand(lines, &mut diags);
+
+	// What is found on each line is kept with it, to be given the line's
+	// include and macro call notes at the end.
+	let mut found: Vec<Vec<Diagnostic>> = lines.iter().map(|_| Vec::new()).collect();
+	let mut stmts: Vec<Stmt> = lines.iter().zip(found.iter_mut()).map(|(l, diags)| statement(l, options.legacy_labels, diags)).collect();
+
+	// First pass over lines to fill in symbol_table, and work out where
+	// everything goes.
+	let mut symbol_table: HashMap<String, Symbol> = options.defines.iter()
+		.map(|(name, value)| (name.clone(), Symbol { value: *value, at: None, constant: true }))
+		.collect();
+	let mut lc: usize = 0;
+	for (st, diags) in stmts.iter_mut().zip(found.iter_mut()) {
+		let l = st.line;
+		st.addr = lc;
+		st.size = match st.words.first() {
+			None => 0,
+			Some(token_0) if token_0.text.starts_with('.') => {
+				let args = args(l, &st.words[1..]);
+				for missing in args.iter().filter(|a| a.text.is_empty()) {
+					diags.push(Diagnostic::error(l.span(missing.col, 1), "missing value".to_string()));
+				}
+				match directive(token_0.text) {
+					Some(Directive::Org) => {
+						if args.len() != 1 {
+							diags.push(Diagnostic::error(l.at(token_0), ".org takes one address".to_string()));
+						} else if let Some(addr) = known(l, &args[0], &symbol_table, st.addr, diags) {
+							if !(0..ram as i64).contains(&addr) {
+								let msg = format!("address {} is outside ram (0 to {})", addr, ram as i64 - 1);
+								diags.push(Diagnostic::error(l.at(&args[0]), msg));
+							} else {
+								st.addr = addr as usize;
+							}
+						}
+						0
+					},
+					Some(Directive::Byte) => {
+						if args.is_empty() {
+							diags.push(Diagnostic::error(l.after(token_0), format!("{} needs at least one value", token_0.text)));
+						}
+						args.len()
+					},
+					Some(Directive::Fill) => {
+						if args.is_empty() || args.len() > 2 {
+							diags.push(Diagnostic::error(l.at(token_0), ".fill takes a count, and optionally a value".to_string()));
+							0
+						} else {
+							match known(l, &args[0], &symbol_table, st.addr, diags) {
+								Some(count) if (0..=ram as i64).contains(&count) => count as usize,
+								Some(count) => {
+									let msg = format!("cannot fill {} bytes (0 to {})", count, ram);
+									diags.push(Diagnostic::error(l.at(&args[0]), msg));
+									0
+								},
+								None => 0,
+							}
+						}
+					},
+					Some(Directive::Equ) => {
+						if args.len() != 2 {
+							diags.push(Diagnostic::error(l.at(token_0), ".equ takes a name and a value".to_string()));
+						} else if !is_label_name(args[0].text) {
+							let msg = format!("{:?} cannot be a constant name - names are a letter or _, then letters, digits and _, and not an instruction", args[0].text);
+							diags.push(Diagnostic::error(l.at(&args[0]), msg));
+						} else if let Some(value) = known(l, &args[1], &symbol_table, st.addr, diags) {
+							define(&mut symbol_table, l, &args[0], value, true, diags);
+						}
+						0
+					},
+					None => {
+						diags.push(Diagnostic::error(l.at(token_0), format!("unknown directive {:?}", token_0.text)));
+						0
+					},
+				}
+			},
+			Some(_) => 1,
+		};
+		// After any .org, so a label on the same line names the new address.
+		if let Some(label) = &st.label {
+			define(&mut symbol_table, l, label, st.addr as i64, false, diags);
+		}
+		lc = st.addr + st.size;
+	}
+
+	// Second pass to actually build machine code.
+	let mut mc: [u8; RAM_SIZE] = [0; RAM_SIZE];
+	let mut used: Vec<Option<Diagnostic>> = vec![None; RAM_SIZE]; // Note pointing at the line that put each byte there.
+	let mut overflow = false;
+	for (st, diags) in stmts.iter_mut().zip(found.iter_mut()) {
+		let (l, words) = (st.line, &st.words);
+		if words.is_empty() {
+			continue;
+		}
+		let token_0 = &words[0];
+		let scope = Scope { symbols: &symbol_table, here: st.addr as i64, early: false };
+		let bytes: Vec<u8> = if token_0.text.starts_with('.') {
+			let args = args(l, &words[1..]);
+			match directive(token_0.text) {
+				Some(Directive::Byte) => args.iter().map(|a| byte_value(l, a, &scope, diags)).collect(),
+				Some(Directive::Fill) => {
+					let v = args.get(1).map_or(0, |a| byte_value(l, a, &scope, diags));
+					vec![v; st.size]
+				},
+				_ => Vec::new(),
+			}
+		} else if is_number(token_0.text) || token_0.text.starts_with('(') {
+			let data = joined(l, words).unwrap_or(Word { text: "", col: 0 });
+			vec![byte_value(l, &data, &scope, diags)]
+		} else if let Ok(op) = token_0.text.parse::<Opcode>() {
+			if op.operands() == 0 { // 0 Operand Op Code.
+				if words.len() > 1 {
+					diags.push(Diagnostic::error(l.at(&words[1]), format!("{} takes no operand", op)));
+				}
+				vec![(op as u8) << 4]
+			} else { // Single Operand Op Code.
+				match joined(l, &words[1..]) {
+					None => {
+						diags.push(Diagnostic::error(l.after(token_0), format!("{} needs an operand", op)));
+						vec![(op as u8) << 4]
+					},
+					Some(operand) => {
+						let limit = if op.info().addresses() { ram } else { RAM_SIZE };
+						vec![((op as u8) << 4) | operand_value(l, &operand, &scope, limit, diags)]
+					},
+				}
+			}
+		} else {
+			diags.push(Diagnostic::error(l.at(token_0), format!("unknown instruction {:?}", token_0.text)));
+			vec![0]
+		};
+		for (i, &byte) in bytes.iter().enumerate() {
+			let addr = st.addr + i;
+			if addr >= ram {
+				if !overflow {
+					let msg = format!("program does not fit in {} bytes of ram - this would go at address {}", ram, addr);
+					diags.push(Diagnostic::error(l.at(token_0), msg));
+					overflow = true;
+				}
+				break;
+			}
+			match &used[addr] {
+				Some(first) => {
+					let mut d = Diagnostic::error(l.at(token_0), format!("address {} is already used", addr));
+					d.notes.push(first.clone());
+					diags.push(d);
+					break;
+				},
+				None => {
+					let mut first = Diagnostic::note(l.at(token_0), format!("address {} is first used here", addr));
+					first.notes.extend(l.calls.iter().cloned());
+					used[addr] = Some(first);
+					mc[addr] = byte;
+				},
+			}
+		}
+		st.bytes = bytes;
+	}
+
+	for (l, line_diags) in lines.iter().zip(found) {
+		for mut d in line_diags {
+			d.notes.extend(l.calls.iter().cloned());
+			diags.push(d);
+		}
+	}
+	if diags.iter().any(|d| d.level == Level::Error) {
+		return diags;
+	}
+	if let Err(e) = fs::write(out_name, mc) {
+		diags.push(Diagnostic::error(None, format!("cannot write {:?}: {}", out_name, e)));
+		return diags;
+	}
+	// Which addresses the program fills in, so the emulator can tell data
+	// that happens to be 0 from ram the program never sets.
+	if options.used {
+		let cells: Vec<String> = (0..RAM_SIZE).filter(|a| used[*a].is_some()).map(|a| a.to_string()).collect();
+		let used_name = format!("{}.used", out_name);
+		if let Err(e) = fs::write(&used_name, cells.join(",") + "\n") {
+			diags.push(Diagnostic::error(None, format!("cannot write {:?}: {}", used_name, e)));
+			return diags;
+		}
+	}
+	if let Some(list_name) = &options.listing {
+		if let Err(e) = fs::write(list_name, listing::listing(file_name, &stmts, &symbol_table)) {
+			diags.push(Diagnostic::error(None, format!("cannot write {:?}: {}", list_name, e)));
+		}
+	}
+	diags
+}
+
+// Adds a label or constant to the symbol table, unless it is already there.
+fn define(symbol_table: &mut HashMap<String, Symbol>, l: &Line, name: &Word, value: i64, constant: bool, diags: &mut Vec<Diagnostic>) {
+	if let Some(prev) = symbol_table.get(name.text) {
+		let msg = match &prev.at {
+			Some((file, line)) if *file == l.file => format!("{:?} is already defined, on line {}", name.text, line),
+			Some((file, line)) => format!("{:?} is already defined, at {}:{}", name.text, file, line),
+			None => format!("{:?} is already defined, with -D", name.text),
+		};
+		diags.push(Diagnostic::error(l.at(name), msg));
+	} else {
+		symbol_table.insert(name.text.to_string(), Symbol { value, at: Some((l.file.clone(), l.number)), constant });
+	}
+}
+
+// The value of an expression, or None after reporting why it has none.
+// Empty words have already been reported as missing.
+fn value(l: &Line, w: &Word, scope: &Scope, diags: &mut Vec<Diagnostic>) -> Option<i64> {
+	if w.text.is_empty() {
+		return None;
+	}
+	match expr::eval(l, w, scope) {
+		Ok(v) => Some(v),
+		Err(d) => {
+			diags.push(*d);
+			None
+		},
+	}
+}
+
+// A value needed during the first pass, at address `here`. It can only
+// use names defined further up.
+fn known(l: &Line, w: &Word, symbol_table: &HashMap<String, Symbol>, here: usize, diags: &mut Vec<Diagnostic>) -> Option<i64> {
+	value(l, w, &Scope { symbols: symbol_table, here: here as i64, early: true }, diags)
+}
+
+// A data byte. Anything from -128 to 255 fits, negative numbers as two's
+// complement.
+fn byte_value(l: &Line, w: &Word, scope: &Scope, diags: &mut Vec<Diagnostic>) -> u8 {
+	let v = match value(l, w, scope, diags) {
+		Some(v) => v,
+		None => return 0,
+	};
+	if !(-128..=255).contains(&v) {
+		let msg = if is_literal(w.text) {
+			format!("{} does not fit in a byte (-128 to 255)", w.text)
+		} else {
+			format!("{:?} is {}, which does not fit in a byte (-128 to 255)", w.text, v)
+		};
+		diags.push(Diagnostic::error(l.at(w), msg));
+	}
+	v as u8
+}
+
+// The value of an instruction's operand, checked to fit the 4 bit operand
+// field. Anything that does not is an error, except a plain label - that
+// is only warned about, as it has an address that the program cannot
+// reach anyway, and is cut down to its low 4 bits. Addresses must also be
+// below `limit`, the end of the ram the program may use.
+fn operand_value(l: &Line, operand: &Word, scope: &Scope, limit: usize, diags: &mut Vec<Diagnostic>) -> u8 {
+	let v = match value(l, operand, scope, diags) {
+		Some(v) => v,
+		None => return 0,
+	};
+	let label = scope.symbols.get(operand.text).is_some_and(|sym| !sym.constant);
+	// Negative numbers are stored as two's complement, in the operand's 4 bits.
+	let min = -(OPERAND_MAX as i64 + 1) / 2;
+	let v = if (min..0).contains(&v) { v & OPERAND_MAX as i64 } else { v };
+	if (0..limit as i64).contains(&v) {
+		// Fits.
+	} else if (0..=OPERAND_MAX as i64).contains(&v) {
+		let msg = format!("address {} is outside the {} bytes of ram the program may use", v, limit);
+		diags.push(Diagnostic::error(l.at(operand), msg));
+	} else if label {
+		let msg = format!("label {:?} is at address {}, which does not fit in a 4 bit operand - it is cut to {}",
+			operand.text, v, v & OPERAND_MAX as i64);
+		diags.push(Diagnostic::warning(l.at(operand), msg));
+	} else if is_literal(operand.text) {
+		let msg = format!("{} does not fit in a 4 bit operand ({} to {})", operand.text, min, OPERAND_MAX);
+		diags.push(Diagnostic::error(l.at(operand), msg));
+	} else {
+		let msg = format!("{:?} is {}, which does not fit in a 4 bit operand ({} to {})", operand.text, v, min, OPERAND_MAX);
+		diags.push(Diagnostic::error(l.at(operand), msg));
+	}
+	(v & OPERAND_MAX as i64) as u8
+}
+
+fn is_symbol(v: &str) -> bool {
+	!is_op_code(v) & !is_number(v) & !v.starts_with('.')
+}
+
+fn is_op_code(v: &str) -> bool {
+	v.parse::<Opcode>().is_ok()
+}
+
+// Whether a word is written as a number, right or not.
+fn is_number(v: &str) -> bool {
+	number(v).is_some()
+}
+
+// Whether a word is a number, and nothing else.
+fn is_literal(v: &str) -> bool {
+	integer(v).is_some_and(|r| r.is_ok())
+}
+
+// Reads a number - decimal, hex (0x1F or $1F), binary (0b0101 or %0101),
+// or a character ('A'). Digits may be grouped with _. Negative numbers
+// are stored as two's complement, so anything from -128 to 255 fits.
+// None if the word does not look like a number at all.
+fn number(v: &str) -> Option<Result<u8, String>> {
+	Some(integer(v)?.and_then(|value| match value {
+		-128..=255 => Ok(value as u8),
+		_ => Err(format!("{} does not fit in a byte (-128 to 255)", v)),
+	}))
+}
+
+// A number, as number reads them, before it has to fit in a byte.
+fn integer(v: &str) -> Option<Result<i64, String>> {
+	if let Some(inner) = v.strip_prefix('\'') {
+		return Some(character(v, inner).map(i64::from));
+	}
+	let (neg, digits) = match v.strip_prefix('-') {
+		Some(rest) => (true, rest),
+		None => (false, v),
+	};
+	let (radix, digits) = if let Some(d) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")).or(digits.strip_prefix('$')) {
+		(16, d)
+	} else if let Some(d) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")).or(digits.strip_prefix('%')) {
+		(2, d)
+	} else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+		(10, digits)
+	} else {
+		return None;
+	};
+	let digits = digits.replace('_', "");
+	let value = match i64::from_str_radix(&digits, radix) {
+		Ok(value) if !digits.starts_with(['+', '-']) => value,
+		Err(e) if *e.kind() == std::num::IntErrorKind::PosOverflow => return Some(Err(format!("{} is too big", v))),
+		_ => return Some(Err(format!("{:?} is not a valid number", v))),
+	};
+	Some(Ok(if neg { -value } else { value }))
+}
+
+// A character literal, from just after its opening quote. Knows the
+// escapes \\, \', \n, \t and \0.
+fn character(v: &str, inner: &str) -> Result<u8, String> {
+	let body = inner.strip_suffix('\'').ok_or(format!("{} is missing its closing quote", v))?;
+	let c = match body {
+		"\\\\" => '\\',
+		"\\'" => '\'',
+		"\\n" => '\n',
+		"\\t" => '\t',
+		"\\0" => '\0',
+		_ => {
+			let mut chars = body.chars();
+			match (chars.next(), chars.next()) {
+				(Some(c), None) if c != '\\' => c,
+				_ => return Err(format!("{} should be one character", v)),
+			}
+		},
+	};
+	u8::try_from(c as u32).map_err(|_| format!("{} does not fit in a byte", v))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+	use super::*;
+
+	// A line of source, as if read from test.ebc.
+	pub(in crate::beebc::asm) fn line(text: &str) -> Line {
+		Line { file: "test.ebc".to_string(), number: 1, text: text.to_string(), calls: Vec::new(), expanded: false, role: Role::Code }
+	}
+
+	// Writes `text` to a file of its own, and returns its path.
+	pub(in crate::beebc::asm) fn temp_file(name: &str, text: &str) -> String {
+		let dir = std::env::temp_dir().join(format!("beebasm-test-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join(name);
+		fs::write(&path, text).unwrap();
+		path.display().to_string()
+	}
+
+	// Assembles `text`, giving the image if it was written, and everything reported.
+	fn build(name: &str, text: &str, options: &Options) -> (Option<Vec<u8>>, Vec<Diagnostic>) {
+		let src = temp_file(&format!("{}.ebc", name), text);
+		let out = format!("{}.bin", src);
+		let _ = fs::remove_file(&out);
+		let diags = assemble(&src, &out, options);
+		(fs::read(&out).ok(), diags)
+	}
+
+	fn errors(diags: &[Diagnostic]) -> Vec<&str> {
+		diags.iter().filter(|d| d.level == Level::Error).map(|d| d.message.as_str()).collect()
+	}
+
+	#[test]
+	fn integer_prefixes() {
+		assert_eq!(integer("42"), Some(Ok(42)));
+		assert_eq!(integer("0x2A"), Some(Ok(42)));
+		assert_eq!(integer("0X2a"), Some(Ok(42)));
+		assert_eq!(integer("$2A"), Some(Ok(42)));
+		assert_eq!(integer("0b101010"), Some(Ok(42)));
+		assert_eq!(integer("%0010_1010"), Some(Ok(42)));
+		assert_eq!(integer("1_000"), Some(Ok(1000)));
+		assert_eq!(integer("-$10"), Some(Ok(-16)));
+	}
+
+	#[test]
+	fn integer_not_numbers() {
+		assert_eq!(integer("LOOP"), None);
+		assert_eq!(integer("_1"), None);
+		assert_eq!(integer("-"), None);
+		assert!(matches!(integer("0x"), Some(Err(_))));
+		assert!(matches!(integer("$G1"), Some(Err(_))));
+		assert!(matches!(integer("%102"), Some(Err(_))));
+		assert!(matches!(integer("12AB"), Some(Err(_))));
+		assert!(matches!(integer("0x-1"), Some(Err(_))));
+		assert_eq!(integer("99999999999999999999"), Some(Err("99999999999999999999 is too big".to_string())));
+	}
+
+	#[test]
+	fn characters() {
+		assert_eq!(integer("'A'"), Some(Ok(65)));
+		assert_eq!(integer("' '"), Some(Ok(32)));
+		assert_eq!(integer("';'"), Some(Ok(59)));
+		assert_eq!(integer("'\\n'"), Some(Ok(10)));
+		assert_eq!(integer("'\\0'"), Some(Ok(0)));
+		assert_eq!(integer("'\\''"), Some(Ok(39)));
+		assert_eq!(integer("'\\\\'"), Some(Ok(92)));
+		assert!(matches!(integer("'AB'"), Some(Err(_))));
+		assert!(matches!(integer("'A"), Some(Err(_))));
+		assert!(matches!(integer("'\\'"), Some(Err(_))));
+		assert!(matches!(integer("'€'"), Some(Err(_))));
+	}
+
+	#[test]
+	fn byte_range() {
+		assert_eq!(number("255"), Some(Ok(255)));
+		assert_eq!(number("-1"), Some(Ok(0xFF)));
+		assert_eq!(number("-128"), Some(Ok(0x80)));
+		assert!(matches!(number("256"), Some(Err(_))));
+		assert!(matches!(number("-129"), Some(Err(_))));
+		assert_eq!(number("NAME"), None);
+	}
+
+	#[test]
+	fn words_stop_at_comments() {
+		let texts = |text: &str| words(text).iter().map(|w| w.text.to_string()).collect::<Vec<_>>();
+		assert_eq!(texts("LOOP:\tLDA  X ; note"), ["LOOP:", "LDA", "X"]);
+		assert_eq!(texts("OUT // note"), ["OUT"]);
+		assert_eq!(texts("LDI ';' ; semicolon"), ["LDI", "';'"]);
+		assert_eq!(texts("LDI ' '"), ["LDI", "' '"]);
+		assert_eq!(words("  LDA X")[1].col, 6);
+	}
+
+	#[test]
+	fn labels_need_a_colon() {
+		let l = line("LOOP: JMP LOOP");
+		let st = statement(&l, false, &mut Vec::new());
+		assert_eq!(st.label.map(|w| w.text), Some("LOOP"));
+		assert_eq!(st.words.iter().map(|w| w.text).collect::<Vec<_>>(), ["JMP", "LOOP"]);
+
+		let l = line("  otu");
+		let mut diags = Vec::new();
+		statement(&l, false, &mut diags);
+		assert_eq!(diags[0].level, Level::Error);
+		let mut diags = Vec::new();
+		let st = statement(&l, true, &mut diags);
+		assert_eq!(diags[0].level, Level::Warning);
+		assert_eq!(st.label.map(|w| w.text), Some("otu"));
+
+		let mut diags = Vec::new();
+		statement(&line("LDA: OUT"), false, &mut diags);
+		assert_eq!(diags.len(), 1);
+	}
+
+	#[test]
+	fn operands_fit_in_4_bits() {
+		let (image, diags) = build("operands", "LDI 15\nLDI $F\nLDI 16\nLDI -9\nLDI 20\n", &Options::default());
+		assert!(image.is_none());
+		assert_eq!(errors(&diags), ["16 does not fit in a 4 bit operand (-8 to 15)", "-9 does not fit in a 4 bit operand (-8 to 15)",
+			"20 does not fit in a 4 bit operand (-8 to 15)"]);
+
+		let (image, _) = build("operands_ok", "LDI 15\nLDA 0\nJMP %1111\nLDI -1\nLDI -8\n", &Options::default());
+		assert_eq!(&image.unwrap()[..5], [0x5F, 0x10, 0x6F, 0x5F, 0x58]);
+	}
+
+	#[test]
+	fn label_past_15_is_warned_about() {
+		let (image, diags) = build("far_label", ".org 15\nOUT\nFAR:\nJMP FAR\n", &Options::default());
+		// Too far to fit, but only warned about as a label.
+		assert!(image.is_none());
+		assert!(diags.iter().any(|d| d.level == Level::Warning && d.message.contains("FAR")));
+		assert_eq!(errors(&diags).len(), 1);
+	}
+
+	#[test]
+	fn data_bytes() {
+		let (image, diags) = build("data", ".byte -1, 255, 'A', 1+1\n.fill 2, $7\nX: .equ Y, 3\n.db Y\n", &Options::default());
+		assert!(errors(&diags).is_empty());
+		assert_eq!(&image.unwrap()[..7], [0xFF, 0xFF, 0x41, 0x02, 0x07, 0x07, 0x03]);
+	}
+
+	#[test]
+	fn overlapping_bytes_point_at_the_first() {
+		let (_, diags) = build("overlap", "OUT\n.org 0\nHLT\n", &Options::default());
+		let d = diags.iter().find(|d| d.level == Level::Error).unwrap();
+		assert_eq!(d.message, "address 0 is already used");
+		assert_eq!(d.notes[0].span.as_ref().map(|s| s.line), Some(1));
+	}
+
+	#[test]
+	fn ram_size_limits_addresses() {
+		let options = Options { ram_size: Some(4), ..Options::default() };
+		let (image, diags) = build("ram_size", "LDA 3\nLDI 9\nJMP 4\n", &options);
+		assert!(image.is_none());
+		assert_eq!(errors(&diags), ["address 4 is outside the 4 bytes of ram the program may use"]);
+		let (_, diags) = build("ram_size_fit", ".fill 5\n", &options);
+		assert_eq!(errors(&diags), ["cannot fill 5 bytes (0 to 4)"]);
+	}
+
+	#[test]
+	fn used_cells_only_when_asked() {
+		let src = temp_file("used.ebc", "OUT\n.org 3\n.byte 0\n");
+		let out = format!("{}.bin", src);
+		let _ = fs::remove_file(format!("{}.used", out));
+		assemble(&src, &out, &Options::default());
+		assert!(fs::read_to_string(format!("{}.used", out)).is_err());
+		assemble(&src, &out, &Options { used: true, ..Options::default() });
+		assert_eq!(fs::read_to_string(format!("{}.used", out)).unwrap(), "0,3\n");
+	}
+
+	#[test]
+	fn columns_count_characters() {
+		let (_, diags) = build("columns", "; ÖÖ\n\tLDI 'Ö' ; Ö\n\tJMP ÖÖ\n", &Options::default());
+		let spans: Vec<(usize, usize, usize)> = diags.iter()
+			.filter_map(|d| d.span.as_ref().map(|s| (s.line, s.col, s.len)))
+			.collect();
+		assert_eq!(spans, [(2, 6, 3), (3, 6, 1)]);
+		assert!(diags[1].to_string().ends_with("\t    ^\n"), "{}", diags[1]);
+	}
+
+	#[test]
+	fn defines() {
+		let mut options = Options::default();
+		options.define("SPEED=$10").unwrap();
+		options.define("FAST").unwrap();
+		assert_eq!(options.defines, [("SPEED".to_string(), 16), ("FAST".to_string(), 1)]);
+		assert!(options.define("1X=2").is_err());
+		assert!(options.define("X=Y").is_err());
+	}
+}
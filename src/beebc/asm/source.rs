@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::{args, expr, is_label_name, joined, words, Diagnostic, Line, Options, Role, Span, Symbol, Word};
+use expr::Scope;
+
+// An open .if or .ifdef.
+struct Cond {
+	outer:  bool, // Whether the lines around the block are being kept.
+	taking: bool, // Whether the lines in it, so far, are.
+	taken:  bool, // Whether either branch has been.
+	other:  bool, // Seen the .else.
+	at:     Option<Span>,
+}
+
+struct Loader<'a> {
+	options:   &'a Options,
+	constants: HashMap<String, Symbol>, // From -D and .equ, so far.
+	open:      Vec<PathBuf>,            // Files being read, outermost first.
+	out:       Vec<Line>,
+	diags:     &'a mut Vec<Diagnostic>,
+}
+
+// Reads the lines of `file_name`, with any files it includes read in
+// where they are included, and only the kept branch of each conditional
+// block to be assembled. Conditions can use constants given on the command
+// line, or defined by .equ further up - labels are not known yet. The
+// directives, and the branches not taken, stay in to be listed.
+pub(super) fn load(file_name: &str, options: &Options, diags: &mut Vec<Diagnostic>) -> Vec<Line> {
+	let constants = options.defines.iter()
+		.map(|(name, value)| (name.clone(), Symbol { value: *value, at: None, constant: true }))
+		.collect();
+	let mut loader = Loader { options, constants, open: Vec::new(), out: Vec::new(), diags };
+	if let Err(e) = loader.file(Path::new(file_name), Vec::new()) {
+		loader.diags.push(Diagnostic::error(None, format!("cannot read {:?}: {}", file_name, e)));
+	}
+	loader.out
+}
+
+impl Loader<'_> {
+	// An error on a line, noting where it was included from.
+	fn error(&mut self, l: &Line, span: Option<Span>, msg: String) {
+		let mut d = Diagnostic::error(span, msg);
+		d.notes.extend(l.calls.iter().cloned());
+		self.diags.push(d);
+	}
+
+	fn file(&mut self, path: &Path, calls: Vec<Diagnostic>) -> std::io::Result<()> {
+		let source = fs::read_to_string(path)?;
+		self.open.push(fs::canonicalize(path)?);
+		let name = path.display().to_string();
+		let mut conds: Vec<Cond> = Vec::new();
+		for (i, text) in source.lines().enumerate() {
+			let mut l = Line { file: name.clone(), number: i + 1, text: text.to_string(), calls: calls.clone(), expanded: false, role: Role::Code };
+			let ws = words(&l.text);
+			let keep = conds.last().is_none_or(|c| c.taking);
+			let directive = ws.first().map(|w| w.text.to_ascii_lowercase());
+			let listed = |outer: bool| if outer { Role::Listed } else { Role::Skipped };
+			match directive.as_deref() {
+				Some(".if") | Some(".ifdef") => {
+					let taking = keep && self.condition(&l, &ws);
+					conds.push(Cond { outer: keep, taking, taken: taking, other: false, at: l.at(&ws[0]) });
+					l.role = listed(keep);
+				},
+				Some(".else") => {
+					self.no_args(&l, &ws);
+					match conds.last_mut() {
+						None => self.error(&l, l.at(&ws[0]), ".else without an .if".to_string()),
+						Some(c) if c.other => self.error(&l, l.at(&ws[0]), "a second .else for the same .if".to_string()),
+						Some(c) => {
+							c.other = true;
+							c.taking = c.outer && !c.taken;
+						},
+					}
+					l.role = listed(conds.last().is_none_or(|c| c.outer));
+				},
+				Some(".endif") => {
+					self.no_args(&l, &ws);
+					match conds.pop() {
+						None => self.error(&l, l.at(&ws[0]), ".endif without an .if".to_string()),
+						Some(c) => l.role = listed(c.outer),
+					}
+				},
+				_ if !keep => l.role = Role::Skipped,
+				Some(".include") => {
+					self.out.push(Line { role: Role::Listed, ..l.clone() });
+					self.include(&l, &ws, path);
+					continue;
+				},
+				Some(".equ") => self.constant(&l, &ws),
+				_ => {},
+			}
+			self.out.push(l);
+		}
+		// Blocks do not carry on past the end of a file.
+		for c in conds {
+			let mut d = Diagnostic::error(c.at, ".if without an .endif".to_string());
+			d.notes.extend(calls.iter().cloned());
+			self.diags.push(d);
+		}
+		self.open.pop();
+		Ok(())
+	}
+
+	fn no_args(&mut self, l: &Line, ws: &[Word]) {
+		if let Some(extra) = ws.get(1) {
+			self.error(l, l.at(extra), format!("{} takes nothing after it", ws[0].text));
+		}
+	}
+
+	// Whether an .if or .ifdef holds. False, after reporting why, if it
+	// cannot be worked out.
+	fn condition(&mut self, l: &Line, ws: &[Word]) -> bool {
+		if ws[0].text.eq_ignore_ascii_case(".ifdef") {
+			return match ws {
+				[_, name] if is_label_name(name.text) => self.constants.contains_key(name.text),
+				_ => {
+					self.error(l, l.at(&ws[0]), ".ifdef takes one name".to_string());
+					false
+				},
+			};
+		}
+		let cond = match joined(l, &ws[1..]) {
+			Some(cond) => cond,
+			None => {
+				self.error(l, l.after(&ws[0]), ".if needs a condition".to_string());
+				return false;
+			},
+		};
+		let scope = Scope { symbols: &self.constants, here: 0, early: true };
+		match expr::eval(l, &cond, &scope) {
+			Ok(v) => v != 0,
+			Err(d) => {
+				let mut d = *d;
+				d.notes.extend(l.calls.iter().cloned());
+				self.diags.push(d);
+				false
+			},
+		}
+	}
+
+	// Notes the value of a constant for later conditions. Anything wrong
+	// with it is left for the first pass to report.
+	fn constant(&mut self, l: &Line, ws: &[Word]) {
+		if let [name, value] = args(l, &ws[1..]).as_slice() {
+			let scope = Scope { symbols: &self.constants, here: 0, early: true };
+			if let Ok(v) = expr::eval(l, value, &scope) {
+				if is_label_name(name.text) && !self.constants.contains_key(name.text) {
+					self.constants.insert(name.text.to_string(), Symbol { value: v, at: Some((l.file.clone(), l.number)), constant: true });
+				}
+			}
+		}
+	}
+
+	// Reads in a file named in quotes, looked for next to the file that
+	// includes it, then in each include directory in turn.
+	fn include(&mut self, l: &Line, ws: &[Word], from: &Path) {
+		let arg = match joined(l, &ws[1..]) {
+			Some(arg) => arg,
+			None => {
+				self.error(l, l.after(&ws[0]), ".include needs a file name, in double quotes".to_string());
+				return;
+			},
+		};
+		let name = match arg.text.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+			Some(name) if !name.is_empty() => name,
+			_ => {
+				self.error(l, l.at(&arg), ".include needs a file name, in double quotes".to_string());
+				return;
+			},
+		};
+		let here = from.parent().unwrap_or(Path::new(""));
+		let mut tried = vec![here.join(name)];
+		tried.extend(self.options.include_dirs.iter().map(|d| Path::new(d).join(name)));
+		let path = match tried.iter().find(|p| p.is_file()) {
+			Some(path) => path.clone(),
+			None => {
+				let dirs: Vec<String> = tried.iter().map(|p| p.display().to_string()).collect();
+				self.error(l, l.at(&arg), format!("cannot find {:?} - tried {}", name, dirs.join(", ")));
+				return;
+			},
+		};
+		if fs::canonicalize(&path).is_ok_and(|c| self.open.contains(&c)) {
+			self.error(l, l.at(&arg), format!("{} includes itself", path.display()));
+			return;
+		}
+		let mut calls = vec![Diagnostic::note(l.at(&arg), "included from here".to_string())];
+		calls.extend(l.calls.iter().cloned());
+		if let Err(e) = self.file(&path, calls) {
+			self.error(l, l.at(&arg), format!("cannot read {}: {}", path.display(), e));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::beebc::asm::tests::temp_file;
+
+	// The lines kept to be assembled, and the messages of anything reported.
+	fn kept(name: &str, text: &str, defines: &[(&str, i64)]) -> (Vec<String>, Vec<String>) {
+		let path = temp_file(name, text);
+		let options = Options {
+			defines: defines.iter().map(|(n, v)| (n.to_string(), *v)).collect(),
+			..Options::default()
+		};
+		let mut diags = Vec::new();
+		let lines = load(&path, &options, &mut diags);
+		let code = lines.iter().filter(|l| l.role == Role::Code).map(|l| l.text.clone()).collect();
+		(code, diags.into_iter().map(|d| d.message).collect())
+	}
+
+	#[test]
+	fn if_else() {
+		let text = ".if MODE == 1\nONE\n.else\nOTHER\n.endif\nAFTER";
+		assert_eq!(kept("if1.ebc", text, &[("MODE", 1)]).0, ["ONE", "AFTER"]);
+		assert_eq!(kept("if2.ebc", text, &[("MODE", 2)]).0, ["OTHER", "AFTER"]);
+	}
+
+	#[test]
+	fn nested() {
+		let text = ".if A\n.if B\nAB\n.else\nA_NOT_B\n.endif\n.else\n.if B\nB_NOT_A\n.else\nNEITHER\n.endif\n.endif";
+		assert_eq!(kept("nest1.ebc", text, &[("A", 1), ("B", 1)]).0, ["AB"]);
+		assert_eq!(kept("nest2.ebc", text, &[("A", 1), ("B", 0)]).0, ["A_NOT_B"]);
+		assert_eq!(kept("nest3.ebc", text, &[("A", 0), ("B", 1)]).0, ["B_NOT_A"]);
+		assert_eq!(kept("nest4.ebc", text, &[("A", 0), ("B", 0)]).0, ["NEITHER"]);
+	}
+
+	#[test]
+	fn inner_else_is_not_taken_when_outer_is_skipped() {
+		let text = ".if 0\n.if 0\nX\n.else\nY\n.endif\n.endif\nZ";
+		assert_eq!(kept("skip.ebc", text, &[]).0, ["Z"]);
+	}
+
+	#[test]
+	fn ifdef_and_equ() {
+		let text = ".ifdef FAST\nQUICK\n.endif\n.equ SLOW, 0\n.if SLOW\nLATE\n.endif";
+		assert_eq!(kept("def1.ebc", text, &[("FAST", 1)]).0, ["QUICK", ".equ SLOW, 0"]);
+		assert_eq!(kept("def2.ebc", text, &[]).0, [".equ SLOW, 0"]);
+	}
+
+	#[test]
+	fn skipped_lines_are_kept_for_the_listing() {
+		let path = temp_file("list.ebc", ".if 0\nGONE\n.endif");
+		let mut diags = Vec::new();
+		let roles: Vec<Role> = load(&path, &Options::default(), &mut diags).iter().map(|l| l.role).collect();
+		assert_eq!(roles, [Role::Listed, Role::Skipped, Role::Listed]);
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(kept("e1.ebc", ".else", &[]).1, [".else without an .if"]);
+		assert_eq!(kept("e2.ebc", ".endif", &[]).1, [".endif without an .if"]);
+		assert_eq!(kept("e3.ebc", ".if 1", &[]).1, [".if without an .endif"]);
+		assert_eq!(kept("e4.ebc", ".if 1\n.else\n.else\n.endif", &[]).1, ["a second .else for the same .if"]);
+		assert_eq!(kept("e5.ebc", ".if\n.endif", &[]).1, [".if needs a condition"]);
+		assert_eq!(kept("e6.ebc", ".if LATER\n.endif\n.equ LATER, 1", &[]).1, ["\"LATER\" must be defined before it is used here"]);
+		assert_eq!(kept("e7.ebc", ".endif 1\n", &[]).1, [".endif takes nothing after it", ".endif without an .if"]);
+	}
+
+	#[test]
+	fn includes() {
+		let inner = temp_file("inner.ebc", "INNER");
+		let name = Path::new(&inner).file_name().unwrap().to_str().unwrap().to_string();
+		let (code, diags) = kept("outer.ebc", &format!("BEFORE\n.include \"{}\"\nAFTER", name), &[]);
+		assert!(diags.is_empty());
+		assert_eq!(code, ["BEFORE", "INNER", "AFTER"]);
+		assert_eq!(kept("self.ebc", ".include \"self.ebc\"", &[]).1.len(), 1);
+		assert!(kept("missing.ebc", ".include \"nowhere.ebc\"", &[]).1[0].starts_with("cannot find \"nowhere.ebc\""));
+	}
+}
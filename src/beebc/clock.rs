@@ -0,0 +1,232 @@
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How the machine's clock is driven.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Manual,         // One tick per pulse, from the user.
+    RealTime(f64),  // A fixed frequency, in Hz.
+    Unthrottled,    // As fast as the host can go.
+}
+
+// Parses "manual", "free", or a frequency like "2", "2.5", "500k" or "1M".
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "manual" => return Ok(Mode::Manual),
+            "free"   => return Ok(Mode::Unthrottled),
+            _ => {}
+        }
+        let (num, scale) = match s.chars().last() {
+            Some('k') | Some('K') => (&s[..s.len()-1], 1e3),
+            Some('M')             => (&s[..s.len()-1], 1e6),
+            _                     => (s, 1.0),
+        };
+        match num.parse::<f64>() {
+            Ok(hz) if hz > 0.0 && hz.is_finite() => Ok(Mode::RealTime(hz * scale)),
+            _ => Err(format!("bad clock {:?}, expected manual, free or a frequency", s)),
+        }
+    }
+}
+
+// Achieved vs requested frequency of a clock.
+#[derive(Debug, Clone, Copy)]
+pub struct Report {
+    pub mode:     Mode,
+    pub ticks:    u64,
+    pub elapsed:  Duration,
+    pub dropped:  u64, // Ticks skipped because the host fell too far behind.
+}
+
+impl Report {
+    pub fn achieved(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { self.ticks as f64 / secs } else { 0.0 }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let requested = match self.mode {
+            Mode::Manual       => String::from("manual"),
+            Mode::Unthrottled  => String::from("unthrottled"),
+            Mode::RealTime(hz) => format!("{:.1} Hz", hz),
+        };
+        write!(f, "clock: requested {}, achieved {:.1} Hz ({} ticks in {:.3}s, {} dropped)",
+            requested, self.achieved(), self.ticks, self.elapsed.as_secs_f64(), self.dropped)
+    }
+}
+
+// Ticks handed out at once when unthrottled.
+const FREE_BURST: u64 = 4096;
+
+// Clock source. Real-time ticks are scheduled against an absolute start
+// time, so sleeping late on one tick is made up on the next ones instead
+// of accumulating as drift. When the host falls more than `max_lag` behind,
+// the backlog is dropped rather than run as one long burst.
+pub struct Clock {
+    pub mode:    Mode,
+    pub max_lag: Duration,
+    started:     Instant,  // When the clock was created, for the report.
+    idle:        Duration, // Time spent paused, left out of the report.
+    paused_at:   Option<Instant>,
+    epoch:       Instant,  // Start of the current real-time schedule.
+    scheduled:   u64,      // Ticks run since `epoch`.
+    ticks:       u64,
+    dropped:     u64,
+    pulses:      u64,      // Manual pulses not yet run.
+}
+
+impl Clock {
+    pub fn new(mode: Mode) -> Clock {
+        let now = Instant::now();
+        Clock {
+            mode,
+            max_lag: Duration::from_millis(100),
+            started: now,
+            idle: Duration::ZERO,
+            paused_at: None,
+            epoch: now,
+            scheduled: 0,
+            ticks: 0,
+            dropped: 0,
+            pulses: 0,
+        }
+    }
+
+    fn period(&self) -> Option<Duration> {
+        match self.mode {
+            Mode::RealTime(hz) => Some(Duration::from_secs_f64(1.0 / hz)),
+            _ => None,
+        }
+    }
+
+    // A manual clock pulse. Other modes ignore it.
+    pub fn pulse(&mut self) {
+        if self.mode == Mode::Manual {
+            self.pulses += 1;
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(Instant::now());
+        }
+    }
+
+    // Restarts the real-time schedule from now, so the paused time
+    // is not caught up on.
+    pub fn resume(&mut self) {
+        if let Some(t) = self.paused_at.take() {
+            self.idle += t.elapsed();
+        }
+        self.epoch = Instant::now();
+        self.scheduled = 0;
+    }
+
+    // Number of ticks that should be run now.
+    pub fn due(&mut self) -> u64 {
+        if self.paused() { return 0; }
+        match self.mode {
+            Mode::Manual => self.pulses,
+            Mode::Unthrottled => FREE_BURST,
+            Mode::RealTime(hz) => {
+                let elapsed = self.epoch.elapsed();
+                let owed = (elapsed.as_secs_f64() * hz) as u64;
+                let max_owed = (self.max_lag.as_secs_f64() * hz).max(1.0) as u64;
+                let behind = owed.saturating_sub(self.scheduled);
+                if behind > max_owed {
+                    self.dropped += behind - max_owed;
+                    self.scheduled = owed - max_owed;
+                    return max_owed;
+                }
+                behind
+            }
+        }
+    }
+
+    // Records that one tick was run.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        self.scheduled += 1;
+        if self.mode == Mode::Manual {
+            self.pulses = self.pulses.saturating_sub(1);
+        }
+    }
+
+    // Time until the next tick is due, or None if that is up to the user.
+    pub fn until_next(&self) -> Option<Duration> {
+        if self.paused() { return None; }
+        match self.mode {
+            Mode::Manual => None,
+            Mode::Unthrottled => Some(Duration::ZERO),
+            Mode::RealTime(_) => {
+                let period = self.period().unwrap();
+                let next = self.epoch + period.mul_f64((self.scheduled + 1) as f64);
+                Some(next.saturating_duration_since(Instant::now()))
+            }
+        }
+    }
+
+    // Sleeps until the next tick is due, but no longer than `limit`.
+    pub fn wait(&self, limit: Duration) {
+        let d = match self.until_next() {
+            Some(d) => d.min(limit),
+            None => limit,
+        };
+        if d > Duration::ZERO {
+            thread::sleep(d);
+        }
+    }
+
+    pub fn report(&self) -> Report {
+        Report {
+            mode: self.mode,
+            ticks: self.ticks,
+            elapsed: match self.paused_at {
+                Some(t) => t - self.started - self.idle,
+                None => self.started.elapsed() - self.idle,
+            },
+            dropped: self.dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes() {
+        assert_eq!("manual".parse::<Mode>(), Ok(Mode::Manual));
+        assert_eq!("free".parse::<Mode>(), Ok(Mode::Unthrottled));
+        assert_eq!("10".parse::<Mode>(), Ok(Mode::RealTime(10.0)));
+        assert_eq!("2.5k".parse::<Mode>(), Ok(Mode::RealTime(2500.0)));
+        assert_eq!("300K".parse::<Mode>(), Ok(Mode::RealTime(300_000.0)));
+        assert_eq!("1M".parse::<Mode>(), Ok(Mode::RealTime(1e6)));
+    }
+
+    #[test]
+    fn bad_modes() {
+        for s in ["", "0", "-1", "k", "1m", "inf", "NaN", "fast", "Manual"] {
+            assert!(s.parse::<Mode>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn manual_ticks_once_per_pulse() {
+        let mut clock = Clock::new(Mode::Manual);
+        assert_eq!(clock.due(), 0);
+        clock.pulse();
+        clock.pulse();
+        assert_eq!(clock.due(), 2);
+    }
+}
@@ -0,0 +1,63 @@
+use core::fmt;
+
+// Everything that can go wrong loading or running a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeebcError {
+    // The instruction at `addr` has an op code with no instruction behind it.
+    UndefinedOpcode { addr: u8, op: u8 },
+    // The PC counted past the end of ram and wrapped back to 0.
+    PcOverflow,
+    // A program image is `len` bytes, not exactly the `expected` size of ram.
+    ProgramSize { len: usize, expected: usize },
+    // The instruction at `pc` touched `addr` in a way its protection forbids.
+    Protected { addr: u8, pc: u8, access: Access },
+}
+
+// Ways of touching a ram cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,     // As data.
+    Write,
+    Execute,  // Fetched as an instruction.
+}
+
+impl fmt::Display for BeebcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BeebcError::UndefinedOpcode { addr, op } =>
+                write!(f, "undefined op code {:04b} at address {}", op, addr),
+            BeebcError::PcOverflow =>
+                write!(f, "program counter ran past the end of ram"),
+            BeebcError::ProgramSize { len, expected } =>
+                write!(f, "program is {} bytes, expected {}", len, expected),
+            BeebcError::Protected { addr, pc, access: Access::Read } =>
+                write!(f, "read of execute-only address {:04b} by the instruction at {:04b}", addr, pc),
+            BeebcError::Protected { addr, pc, access: Access::Write } =>
+                write!(f, "write to protected address {:04b} by the instruction at {:04b}", addr, pc),
+            BeebcError::Protected { addr, access: Access::Execute, .. } =>
+                write!(f, "execution of no-execute address {:04b}", addr),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BeebcError {}
+
+// What to do with an op code that has no instruction behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Undefined {
+    #[default]
+    Trap,  // Stop stepping with BeebcError::UndefinedOpcode.
+    Nop,   // Carry on, as if it was a NOP.
+    Halt,  // Halt the clock, as if it was a HLT.
+}
+
+// What to do when the PC runs past the end of ram, and is about to fetch
+// from address 0 again without having jumped there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    #[default]
+    Trap,  // Stop stepping with BeebcError::PcOverflow.
+    Wrap,  // Carry on from address 0, as the counter chips do.
+    Halt,  // Halt the clock, as if it was a HLT.
+}
@@ -1,93 +1,141 @@
+// The widget builders are written with explicit returns and elided
+// lifetimes, and are left that way.
+#![allow(clippy::needless_return, mismatched_lifetime_syntaxes)]
 
 use crate::beebc;
 use crate::beebc::{
-    signal
+    signal,
+    signal::ControlWord,
+    link::System
 };
 use tui::{
     backend::{Backend},
+    Frame,
     Terminal,
     widgets::{Block, Borders, Paragraph, List, ListItem},
-    layout::{Layout, Constraint, Direction},
+    layout::{Layout, Constraint, Direction, Rect},
     style::{Style, Color}
 };
 
-pub fn render<B: Backend>(terminal: &mut Terminal<B>, ebc: &beebc::EBC, ram: [u8; 16],last_cw: u16){
+// `signed` shows the output register as a two's complement number.
+pub fn render<B: Backend>(terminal: &mut Terminal<B>, ebc: &beebc::EBC, ram: [u8; 16],last_cw: ControlWord, signed: bool){
     terminal.draw(|f| {
+        let area = f.size();
+        draw_machine(f, area, ebc, ram, last_cw, signed);
+    }).expect("draw failed.");
+}
+
+// Draws every machine of a linked system side by side.
+pub fn render_system<B: Backend>(terminal: &mut Terminal<B>, sys: &System, signed: bool){
+    terminal.draw(|f| {
+        let n = sys.machines.len() as u32;
+        let constraints: Vec<Constraint> = (0..n).map(|_| Constraint::Ratio(1, n)).collect();
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
-            .margin(1)
-            .constraints([
-                Constraint::Length(16),
-                Constraint::Min(16),
-                Constraint::Length(16),
-            ].as_ref())
+            .constraints(constraints)
             .split(f.size());
-        { // Left Column
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3)
-                ].as_ref())
-                .split(chunks[0]);
 
-            f.render_widget(make_u4register("PC", ebc.pc), chunks[0]);
-            f.render_widget(make_u4register("SC", ebc.sc), chunks[1]);
-            f.render_widget(make_irregister("IR", ebc.ir), chunks[2]);
-            f.render_widget(make_u8register("MAR", ebc.mar), chunks[3]);
-            f.render_widget(make_help_text(), chunks[4]); 
+        for (i, m) in sys.machines.iter().enumerate() {
+            let block = Block::default()
+                .title(format!("M{} (div {})", i, m.div))
+                .borders(Borders::ALL);
+            let inner = block.inner(chunks[i]);
+            f.render_widget(block, chunks[i]);
+            draw_machine(f, inner, &m.ebc, m.ram, m.last_cw, signed);
         }
-        { // Middle "Bus"/{Controlwords, RAM}
+    }).expect("draw failed.");
+}
+
+// Draws a page of text over the whole screen, e.g. a profile report.
+pub fn render_text<B: Backend>(terminal: &mut Terminal<B>, title: &str, text: &str){
+    terminal.draw(|f| {
+        let page = Paragraph::new(text.to_string())
+            .block(Block::default()
+            .title(title.to_string())
+            .borders(Borders::ALL));
+        f.render_widget(page, f.size());
+    }).expect("draw failed.");
+}
+
+fn draw_machine<B: Backend>(f: &mut Frame<B>, area: Rect, ebc: &beebc::EBC, ram: [u8; 16], last_cw: ControlWord, signed: bool){
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .margin(1)
+        .constraints([
+            Constraint::Length(16),
+            Constraint::Min(16),
+            Constraint::Length(16),
+        ].as_ref())
+        .split(area);
+    { // Left Column
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(5)
+            ].as_ref())
+            .split(chunks[0]);
+
+        f.render_widget(make_u4register("PC", ebc.pc), chunks[0]);
+        f.render_widget(make_u4register("SC", ebc.sc), chunks[1]);
+        f.render_widget(make_irregister("IR", ebc.ir), chunks[2]);
+        f.render_widget(make_u8register("MAR", ebc.mar), chunks[3]);
+        f.render_widget(make_u8register("EPC", ebc.epc), chunks[4]);
+        f.render_widget(make_help_text(), chunks[5]); 
+    }
+    { // Middle "Bus"/{Controlwords, RAM}
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(26),
+                Constraint::Length(3)
+            ].as_ref())
+            .split(chunks[1]);
+
+        f.render_widget(make_u8register("BUS", ebc.bus), chunks[0]);
+        
+        {
             let chunks = Layout::default()
-                .direction(Direction::Vertical)
+                .direction(Direction::Horizontal)
                 .constraints([
-                    Constraint::Length(3),
-                    Constraint::Length(20),
-                    Constraint::Length(3)
+                    Constraint::Percentage(50),
+                    Constraint::Percentage(50),
                 ].as_ref())
                 .split(chunks[1]);
 
-            f.render_widget(make_u8register("BUS", ebc.bus), chunks[0]);
-            
-            {
-                let chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([
-                        Constraint::Percentage(50),
-                        Constraint::Percentage(50),
-                    ].as_ref())
-                    .split(chunks[1]);
-
-                f.render_widget(make_ram(ram), chunks[0]);
-                f.render_widget(make_cw_list(last_cw, beebc::decode_instruction(ebc)), chunks[1]);
-            }
-        }
-        { // Right Column
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                    Constraint::Length(3),
-                ].as_ref())
-                .split(chunks[2]);
-
-            f.render_widget(make_u8register("REG A", ebc.reg_a), chunks[0]);
-            f.render_widget(make_u8register("REG B", ebc.reg_b), chunks[1]);
-            f.render_widget(make_u8register("REG ALU", ebc.reg_alu), chunks[2]);
-            f.render_widget(make_u4register("FLAGS", ebc.reg_flgs), chunks[3]);
-            f.render_widget(make_u8register("OUT",   ebc.reg_out), chunks[4]);
-            f.render_widget(make_output("OUT-DEC",   ebc.reg_out), chunks[5]);
+            f.render_widget(make_ram(ram), chunks[0]);
+            f.render_widget(make_cw_list(last_cw, beebc::decode_instruction(ebc)), chunks[1]);
         }
-    }).expect("draw failed.");
+    }
+    { // Right Column
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+            ].as_ref())
+            .split(chunks[2]);
+
+        f.render_widget(make_u8register("REG A", ebc.reg_a), chunks[0]);
+        f.render_widget(make_u8register("REG B", ebc.reg_b), chunks[1]);
+        f.render_widget(make_u8register("REG ALU", ebc.reg_alu), chunks[2]);
+        f.render_widget(make_u4register("FLAGS", ebc.reg_flgs), chunks[3]);
+        f.render_widget(make_u8register("OUT",   ebc.reg_out), chunks[4]);
+        f.render_widget(make_output("OUT-DEC",   ebc.reg_out, signed), chunks[5]);
+        f.render_widget(make_interrupt(ebc), chunks[6]);
+        f.render_widget(make_u8register("IN", ebc.reg_in), chunks[7]);
+    }
 }
 
 fn make_u8register(title: &str, value: u8) -> Paragraph {
@@ -112,38 +160,30 @@ fn make_irregister(title: &str, value: u8) -> Paragraph {
 }
 
 
-fn make_output(title: &str, value: u8) -> Paragraph {
-    return Paragraph::new(format!("{:03}", value))
+fn make_output(title: &str, value: u8, signed: bool) -> Paragraph {
+    let text = if signed { format!("{:+04}", value as i8) } else { format!("{:03}", value) };
+    return Paragraph::new(text)
         .block(Block::default()
         .title(title)
         .borders(Borders::ALL));
 }
 
-fn make_cw_list(last_cw: u16, next_cw: u16) -> List<'static> {
-    fn make_row_str(label:&str, cws1: u16, cws2: u16) -> String {
-        let v1 = if cws1 > 0 { 1 } else { 0 };
-        let v2 = if cws2 > 0 { 1 } else { 0 };
-        return format!("{} |  {}  |  {}  |", label, v1, v2);
+fn make_interrupt(ebc: &beebc::EBC) -> Paragraph<'static> {
+    Paragraph::new(format!("IE {} IRQ {}", ebc.ie as u8, ebc.irq as u8))
+        .block(Block::default()
+        .title("INT")
+        .borders(Borders::ALL))
+}
+
+fn make_cw_list(last_cw: ControlWord, next_cw: ControlWord) -> List<'static> {
+    fn make_row_str(label:&str, v1: bool, v2: bool) -> String {
+        format!("{:^4} |  {}  |  {}  |", label, v1 as u8, v2 as u8)
     }
 
-    let items = [
-            ListItem::new(" sig  last  next | "),
-            ListItem::new(make_row_str("HLT ", last_cw & signal::HLT, next_cw & signal::HLT)),
-            ListItem::new(make_row_str(" MI ", last_cw & signal::MI,  next_cw & signal::MI)),
-            ListItem::new(make_row_str(" RI ", last_cw & signal::RI,  next_cw & signal::RI)),
-            ListItem::new(make_row_str(" RO ", last_cw & signal::RO,  next_cw & signal::RO)),
-            ListItem::new(make_row_str(" IO ", last_cw & signal::IO,  next_cw & signal::IO)),
-            ListItem::new(make_row_str(" II ", last_cw & signal::II,  next_cw & signal::II)),
-            ListItem::new(make_row_str(" AI ", last_cw & signal::AI,  next_cw & signal::AI)),
-            ListItem::new(make_row_str(" AO ", last_cw & signal::AO,  next_cw & signal::AO)),
-            ListItem::new(make_row_str(" EO ", last_cw & signal::EO,  next_cw & signal::EO)),
-            ListItem::new(make_row_str(" SU ", last_cw & signal::SU,  next_cw & signal::SU)),
-            ListItem::new(make_row_str(" BI ", last_cw & signal::BI,  next_cw & signal::BI)),
-            ListItem::new(make_row_str(" OI ", last_cw & signal::OI,  next_cw & signal::OI)),
-            ListItem::new(make_row_str(" CE ", last_cw & signal::CE,  next_cw & signal::CE)),
-            ListItem::new(make_row_str(" CO ", last_cw & signal::CO,  next_cw & signal::CO)),
-            ListItem::new(make_row_str(" J_ ", last_cw & signal::J_,  next_cw & signal::J_)),
-            ListItem::new(make_row_str(" FI ", last_cw & signal::FI,  next_cw & signal::FI)),];
+    let mut items = vec![ListItem::new(" sig  last  next | ")];
+    for (label, sig) in signal::SIGNALS.iter() {
+        items.push(ListItem::new(make_row_str(label, last_cw.has(*sig), next_cw.has(*sig))));
+    }
 
     return List::new(items).block(Block::default().title("CW").borders(Borders::ALL))
     .style(Style::default().fg(Color::White));
@@ -159,6 +199,8 @@ fn make_help_text() -> List<'static> {
     let items = [
         ListItem::new("p - pause"),
         ListItem::new("s - step"),
+        ListItem::new("r - profile"),
+        ListItem::new("x - reset"),
         ListItem::new("q - quit"),
     ];
     return List::new(items).block(Block::default())
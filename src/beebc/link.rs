@@ -0,0 +1,227 @@
+use crate::beebc;
+use crate::beebc::{EBC, signal};
+use crate::beebc::signal::ControlWord;
+use crate::beebc::error::BeebcError;
+use crate::beebc::profile::Profile;
+use crate::beebc::sanitize::{Finding, Sanitizer};
+use crate::beebc::provenance::Provenance;
+use std::fmt;
+use crate::beebc::periph::Peripheral;
+
+// One machine in a linked simulation - its state, ram and peripherals.
+pub struct Machine {
+    pub ebc:     EBC,
+    pub ram:     [u8; 16],
+    pub periphs: Vec<Box<dyn Peripheral>>,
+    pub div:     u32, // Clock divider - ticks once every `div` system ticks.
+    pub last_cw: ControlWord, // Control word of this machine's last tick.
+    pub profile: Option<Profile>, // Performance counters, if profiling.
+    pub sanitizer: Option<Sanitizer>, // Shadow memory, if sanitizing.
+    pub findings: Vec<Finding>, // Sanitizer reports not yet collected.
+    pub provenance: Option<Provenance>, // Data-flow tags, if tracking them.
+    pub breaches: Vec<BeebcError>, // Broken ram access rules, when only warning.
+}
+
+impl Machine {
+    pub fn new(ram: [u8; 16]) -> Machine {
+        Machine {
+            ebc: EBC::default(),
+            ram,
+            periphs: Vec::new(),
+            div: 1,
+            last_cw: signal::NONE,
+            profile: None,
+            sanitizer: None,
+            findings: Vec::new(),
+            provenance: None,
+            breaches: Vec::new(),
+        }
+    }
+
+    // A single clock tick of this machine alone.
+    pub fn tick(&mut self) -> Result<ControlWord, BeebcError> {
+        let watched = self.profile.is_some() || self.sanitizer.is_some() || self.provenance.is_some();
+        let before = if watched { Some(self.ebc.clone()) } else { None };
+        let cw = beebc::step(&mut self.ebc, &mut self.ram)?;
+        self.breaches.extend(self.ebc.breach.take());
+        if let Some(before) = before {
+            if let Some(p) = self.profile.as_mut() {
+                p.record(&before, cw);
+            }
+            if let Some(s) = self.sanitizer.as_mut() {
+                self.findings.extend(s.check(&before, &self.ebc, cw));
+            }
+            if let Some(p) = self.provenance.as_mut() {
+                p.record(&before, &self.ebc, cw);
+            }
+        }
+        for p in self.periphs.iter_mut() {
+            p.tick(&mut self.ebc);
+        }
+        self.last_cw = cw;
+        Ok(cw)
+    }
+}
+
+// A wire from one machine's output register to another's input port.
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    pub from: usize,
+    pub to:   usize,
+    pub irq:  bool, // Raise an interrupt on `to` whenever `from` latches OUT.
+}
+
+// An error from one of the machines in a system.
+#[derive(Debug, Clone, Copy)]
+pub struct MachineError {
+    pub machine: usize,
+    pub error:   BeebcError,
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+// Everything that decides what a system does next. Two equal snapshots
+// mean the system is in a loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    machines: Vec<(EBC, [u8; 16], Vec<u64>)>,
+    phase:    u64, // Position within the cycle of clock dividers.
+}
+
+// Several machines sharing a system clock.
+#[derive(Default)]
+pub struct System {
+    pub machines: Vec<Machine>,
+    pub links:    Vec<Link>,
+    pub ticks:    u64, // System clock ticks so far.
+}
+
+impl System {
+    pub fn new() -> System {
+        System::default()
+    }
+
+    // Every machine has stopped its clock.
+    pub fn halted(&self) -> bool {
+        self.machines.iter().all(|m| m.ebc.hlt)
+    }
+
+    // The state of the whole system, or None if a peripheral cannot say
+    // what state it is in.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        let mut machines = Vec::with_capacity(self.machines.len());
+        let mut period: u64 = 1;
+        for m in self.machines.iter() {
+            let periphs = m.periphs.iter().map(|p| p.state()).collect::<Option<Vec<u64>>>()?;
+            machines.push((m.ebc.clone(), m.ram, periphs));
+            period = lcm(period, m.div.max(1) as u64);
+        }
+        Some(Snapshot { machines, phase: self.ticks % period })
+    }
+
+    // Advances the system clock one tick. Each machine whose divider
+    // lines up with the tick steps once, then the links are propagated.
+    // Returns the index of every machine that latched its output register,
+    // or the first error. The tick is finished either way, so every other
+    // machine has stepped once and the system can be run on from it.
+    pub fn tick(&mut self) -> Result<Vec<usize>, MachineError> {
+        let mut outputs = Vec::new();
+        let mut failure = None;
+        for (i, m) in self.machines.iter_mut().enumerate() {
+            if m.ebc.hlt || !self.ticks.is_multiple_of(m.div.max(1) as u64) {
+                continue;
+            }
+            match m.tick() {
+                Ok(cw) if cw.has(signal::OI) => outputs.push(i),
+                Ok(_) => {},
+                Err(error) => { failure.get_or_insert(MachineError { machine: i, error }); },
+            }
+        }
+
+        for link in self.links.iter() {
+            let value = self.machines[link.from].ebc.reg_out;
+            let dst = &mut self.machines[link.to].ebc;
+            dst.reg_in = value;
+            if link.irq && outputs.contains(&link.from) {
+                dst.irq = true;
+            }
+        }
+
+        self.ticks += 1;
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(outputs),
+        }
+    }
+}
+
+
+fn lcm(a: u64, b: u64) -> u64 {
+    fn gcd(a: u64, b: u64) -> u64 { if b == 0 { a } else { gcd(b, a % b) } }
+    a / gcd(a, b) * b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LDI 7, OUT, HLT - outputs on its 11th tick.
+    const SEND: [u8; 16] = [0x57, 0xE0, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    // An undefined op code, with the signed flags extension off.
+    const BAD: [u8; 16] = [0xC0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    fn run(sys: &mut System, max: usize) -> Vec<(u64, usize)> {
+        let mut outputs = Vec::new();
+        for _ in 0..max {
+            if sys.halted() {
+                break;
+            }
+            for i in sys.tick().unwrap() {
+                outputs.push((sys.ticks, i));
+            }
+        }
+        outputs
+    }
+
+    #[test]
+    fn links_carry_outputs() {
+        let mut sys = System::new();
+        sys.machines.push(Machine::new(SEND));
+        sys.machines.push(Machine::new([0xF0; 16]));
+        sys.links.push(Link { from: 0, to: 1, irq: true });
+        assert_eq!(run(&mut sys, 100), [(11, 0)]);
+        assert_eq!(sys.machines[1].ebc.reg_in, 7);
+        assert!(sys.machines[1].ebc.irq);
+    }
+
+    #[test]
+    fn dividers() {
+        let mut sys = System::new();
+        sys.machines.push(Machine::new(SEND));
+        sys.machines.push(Machine::new(SEND));
+        sys.machines[1].div = 2;
+        assert_eq!(run(&mut sys, 100), [(11, 0), (21, 1)]);
+    }
+
+    #[test]
+    fn an_error_still_finishes_the_tick() {
+        let mut sys = System::new();
+        sys.machines.push(Machine::new(BAD));
+        sys.machines.push(Machine::new(SEND));
+        for _ in 0..2 {
+            sys.tick().unwrap();
+        }
+        let e = sys.tick().unwrap_err();
+        assert_eq!(e.machine, 0);
+        // The other machine stepped, and the clock moved on.
+        assert_eq!((sys.ticks, sys.machines[1].ebc.sc), (3, 3));
+        assert_eq!(sys.tick().unwrap_err().machine, 0);
+        assert_eq!((sys.ticks, sys.machines[1].ebc.sc), (4, 4));
+    }
+}
@@ -0,0 +1,81 @@
+// Exact infinite loop detection. The machine is deterministic, so if its
+// full state ever repeats, it will repeat forever and never halt.
+//
+// Uses Brent's cycle finding algorithm - one saved state, compared with
+// every new one, and replaced whenever the distance to it reaches the
+// next power of two. Any loop is found within a few times its length,
+// without remembering every state seen.
+pub struct LoopDetector<S> {
+    saved: Option<S>,
+    power: u64,
+    steps: u64, // Steps since `saved` was taken.
+}
+
+impl<S: PartialEq> LoopDetector<S> {
+    pub fn new() -> LoopDetector<S> {
+        LoopDetector { saved: None, power: 1, steps: 0 }
+    }
+
+    // Feeds the next state. Returns the length of the loop, in steps,
+    // once a state repeats.
+    pub fn check(&mut self, state: S) -> Option<u64> {
+        self.steps += 1;
+        if self.saved.as_ref() == Some(&state) {
+            return Some(self.steps);
+        }
+        if self.saved.is_none() || self.steps == self.power {
+            self.saved = Some(state);
+            self.power *= 2;
+            self.steps = 0;
+        }
+        None
+    }
+}
+
+impl<S: PartialEq> Default for LoopDetector<S> {
+    fn default() -> LoopDetector<S> {
+        LoopDetector::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds states until a loop is found, giving its length and the number
+    // of states fed.
+    fn find(states: impl Iterator<Item = u64>, max: usize) -> Option<(u64, usize)> {
+        let mut d = LoopDetector::new();
+        for (i, s) in states.take(max).enumerate() {
+            if let Some(n) = d.check(s) {
+                return Some((n, i + 1));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn steady_state() {
+        assert_eq!(find(core::iter::repeat(7), 10), Some((1, 2)));
+    }
+
+    #[test]
+    fn loop_after_a_lead_in() {
+        // 0..9, then 10, 11, 12 over and over.
+        let states = (0..).map(|i| if i < 10 { i } else { 10 + (i - 10) % 3 });
+        let (n, fed) = find(states, 1000).unwrap();
+        assert_eq!(n, 3);
+        assert!(fed < 10 + 4 * 3 + 16, "took {} states", fed);
+    }
+
+    #[test]
+    fn long_loop() {
+        let (n, _) = find((0..).map(|i| i % 1000), 10_000).unwrap();
+        assert_eq!(n, 1000);
+    }
+
+    #[test]
+    fn no_loop() {
+        assert_eq!(find(0.., 10_000), None);
+    }
+}
@@ -1,10 +1,32 @@
 pub mod examples;
 pub mod signal;
 pub mod opcode;
+pub mod periph;
+pub mod error;
+pub mod profile;
+pub mod loops;
+pub mod power;
+pub mod sanitize;
+pub mod protect;
+#[cfg(feature = "std")]
 pub mod asm;
+#[cfg(feature = "std")]
 pub mod gui;
+#[cfg(feature = "std")]
+pub mod link;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod provenance;
+#[cfg(feature = "std")]
+pub mod symbolic;
 
-#[derive(Default, Debug)]
+use opcode::{Instruction, Opcode};
+use signal::{ControlWord, NONE};
+use error::{BeebcError, Overflow, Undefined};
+use protect::Protection;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct EBC { // Full state of the machine, besides ram.
     pub pc:       u8, // Program Counter
     pub sc:       u8, // Micro-[s]tep counter
@@ -14,137 +36,433 @@ pub struct EBC { // Full state of the machine, besides ram.
     pub reg_b:    u8,
     pub reg_alu:  u8, // ALU Output register
     pub reg_out:  u8, // Readout screen register
+    pub reg_in:   u8, // Input port, driven by a linked machine
+    pub in_addr:  Option<u8>, // Address the input port is mapped over, if any
     pub reg_flgs: u8, // Flags / Friendly local game store.
     pub bus:      u8, // The Bus / Jerome Bettis
+    pub epc:      u8, // Saved program counter, restored by RTI
+    pub ivec:     u8, // Interrupt vector - address of the interrupt handler
+    pub irq:      bool, // Interrupt request line, raised by peripherals
+    pub ie:       bool, // Interrupt enable
+    pub int_cycle: bool, // Current instruction cycle is servicing an interrupt
+    pub pc_wrap:  bool, // PC rolled past the end of ram, and has not jumped since
+    pub nv_flags: bool, // Signed flags extension - N and V flags, JN and JV
+    pub undef:    Undefined, // What to do with undefined op codes
+    pub overflow: Overflow, // What to do when the PC runs past the end of ram
+    pub protect:  Protection, // Ram access rules
+    pub breach:   Option<BeebcError>, // Last rule broken, when only warning
     pub hlt:      bool, // Signal computation has halted
 }
 
-// Micro Code - Mapping [op_code][step_count] -> control word
-// Skips the first 2 fetch steps, hard-coded in decode_instruction.
-const UC: [[u16; 6]; 16] = [
-    [0,0,0,0,0,0], // No-op
-    [signal::IO | signal::MI, // LDA
-     signal::RO | signal::AI,
-     0,0,0,0], 
-    [signal::IO | signal::MI, // ADD
-     signal::RO | signal::BI,
-     signal::EO | signal::AI | signal::FI,
-     0,0,0],
-    [signal::IO | signal::MI, // SUB
-     signal::RO | signal::BI,
-     signal::EO | signal::AI | signal::SU | signal::FI,
-     0,0,0],
-    [signal::IO | signal::MI, // STA
-     signal::AO | signal::RI,
-     0,0,0,0],
-    [signal::IO | signal::AI, // LDI
-     0,0,0,0,0],
-    [signal::IO | signal::J_, // JMP
-     0,0,0,0,0],
-    [0,0,0,0,0,0],            // JC - Handled Later
-    [0,0,0,0,0,0],            // JZ - ^
-    [0,0,0,0,0,0], // NOP 
-    [0,0,0,0,0,0], // NOP
-    [0,0,0,0,0,0], // NOP
-    [0,0,0,0,0,0], // NOP
-    [0,0,0,0,0,0], // NOP
-    [signal::AO | signal::OI, // OUT
-     0,0,0,0,0], 
-    [signal::HLT,             // HLT
-     0,0,0,0,0]
+// Interrupt Service Micro Code - Replaces a whole instruction cycle.
+// Saves the PC, acknowledges the request and jumps to the vector.
+const UC_INT: [ControlWord; 8] = [
+    signal::CO.with(signal::XI).with(signal::IA),
+    signal::VO.with(signal::J_),
+    NONE, NONE, NONE, NONE, NONE, NONE
 ];
 
+// The instruction behind an op code, if it is defined on this machine.
+// Instructions from the signed flags extension need it turned on.
+pub(crate) fn defined(ebc: &EBC, op_code: u8) -> Result<&'static Instruction, u8> {
+    let op = Opcode::try_from(op_code)?.info();
+    if op.nv_flags && !ebc.nv_flags {
+        return Err(op_code);
+    }
+    Ok(op)
+}
+
 // The current state of a machine maps to a new control word. 
-pub fn decode_instruction(ebc: & EBC) -> u16 {
+pub fn decode_instruction(ebc: & EBC) -> ControlWord {
+    // Interrupts are only taken at an instruction boundary.
+    if ebc.int_cycle { return UC_INT[ebc.sc as usize]; }
+    if ebc.sc == 0 && ebc.irq && ebc.ie { return UC_INT[0]; }
+
 	// Hardcode Fetch steps.
     if ebc.sc == 0 { return signal::MI | signal::CO; }      
     if ebc.sc == 1 { return signal::RO | signal::II | signal::CE; }
 
-    let op_code = ebc.ir >> 4; // op_code is in the top 4 bits of the IR
-    if ebc.sc == 2 {
-        // Handle Jump-Carry instructions.
-        if(op_code == opcode::JC) && 
-           (ebc.reg_flgs & signal::CF > 0) { return  signal::IO | signal::J_; }
-        if(op_code == opcode::JZ) && 
-           (ebc.reg_flgs & signal::ZF > 0) { return  signal::IO | signal::J_; }
+    // op_code is in the top 4 bits of the IR. Unused op codes do
+    // nothing, or halt, depending on the policy. Trapping is up to step.
+    let op = match defined(ebc, ebc.ir >> 4) {
+        Ok(op) => op,
+        Err(_) if ebc.undef == Undefined::Halt => {
+            return if ebc.sc == 2 { signal::HLT } else { NONE };
+        },
+        Err(_) => return NONE,
+    };
+    // Conditional instructions (JC, JZ) do nothing if their flag is clear.
+    if op.flag != 0 && (ebc.reg_flgs & op.flag) == 0 {
+        return NONE;
     }
     // Otherwise, index into microcode to find the new CW
     // Note - offset the step counter by 2, b/c 0 & 1 are hardcoded
     // ~ Represents the call into the ROM chip. 
-    return UC[op_code as usize][(ebc.sc-2) as usize];
+    op.uc[(ebc.sc-2) as usize]
 }
 
 // The control word is used to update the state of the machine.
-pub fn update_modules(ebc: &mut EBC, cw: u16, mut ram: [u8; 16]){
+pub fn update_modules(ebc: &mut EBC, cw: ControlWord, ram: &mut [u8; 16]){
     // Bus-Write Operations First.
-    if (cw & signal::RO) > 0 { // Ram Out. 
-        ebc.bus = ram[ebc.mar as usize];
+    if cw.has(signal::RO) { // Ram Out. Or the input port, if mapped here.
+        if ebc.in_addr == Some(ebc.mar) {
+            ebc.bus = ebc.reg_in;
+        } else {
+            ebc.bus = ram[ebc.mar as usize];
+        }
     }
-    if (cw & signal::IO) > 0 { // Instruction Out.
+    if cw.has(signal::IO) { // Instruction Out.
         ebc.bus = ebc.ir & 0b00001111;
     }
-    if (cw & signal::AO) > 0 { // A Register Out.
+    if cw.has(signal::AO) { // A Register Out.
         ebc.bus = ebc.reg_a;
     }
-    if (cw & signal::CO) > 0 { // PC Register Out.
+    if cw.has(signal::CO) { // PC Register Out.
         ebc.bus = ebc.pc;
     }
+    if cw.has(signal::XO) { // Saved PC Out.
+        ebc.bus = ebc.epc;
+    }
+    if cw.has(signal::VO) { // Interrupt Vector Out.
+        ebc.bus = ebc.ivec & 0b00001111;
+    }
 
-    // ALU Update - Save the optional result, so you can manage flags
-    let result: Option<u8>; // Will set CF on over or underflow.
-    if (cw & signal::SU) > 0 {
-        result = ebc.reg_a.checked_sub(ebc.reg_b);
+    if ebc.nv_flags {
+        update_alu_signed(ebc, cw);
     } else {
-        result = ebc.reg_a.checked_add(ebc.reg_b);
-    }
-    match result {
-        Some(val) => {
-            ebc.reg_alu = val;
-            if (cw & signal::FI) > 0 && val == 0 {
-                ebc.reg_flgs = signal::ZF;
-            }
-        },
-        None => {
-            if (cw & signal::FI) > 0 {
-                ebc.reg_flgs = signal::CF;
+        // ALU Update - Save the optional result, so you can manage flags
+        // Will set CF on over or underflow.
+        let result: Option<u8> = if cw.has(signal::SU) {
+            ebc.reg_a.checked_sub(ebc.reg_b)
+        } else {
+            ebc.reg_a.checked_add(ebc.reg_b)
+        };
+        match result {
+            Some(val) => {
+                ebc.reg_alu = val;
+                if cw.has(signal::FI) && val == 0 {
+                    ebc.reg_flgs = signal::ZF;
+                }
+            },
+            None => {
+                if cw.has(signal::FI) {
+                    ebc.reg_flgs = signal::CF;
+                }
             }
         }
     }
 
-    if (cw & signal::EO) > 0 {
+    if cw.has(signal::EO) {
         ebc.bus = ebc.reg_alu;
     }
 
     // Bus-Read Operations Second.
-    if (cw & signal::MI) > 0 { // Memory Address Register In.
+    if cw.has(signal::MI) { // Memory Address Register In.
         ebc.mar = ebc.bus & 0b00001111;
     }
-    if (cw & signal::RI) > 0 { // RAM In. To location in MAR from bus.
+    if cw.has(signal::RI) { // RAM In. To location in MAR from bus.
         ram[ebc.mar as usize] = ebc.bus;
     }
-    if (cw & signal::II) > 0 { // Instruction Register In.
+    if cw.has(signal::II) { // Instruction Register In.
         ebc.ir = ebc.bus;
     }
-    if (cw & signal::AI) > 0 { // A Register In.
+    if cw.has(signal::AI) { // A Register In.
         ebc.reg_a = ebc.bus;
     }
-    if (cw & signal::BI) > 0 { // B Register In.
+    if cw.has(signal::BI) { // B Register In.
         ebc.reg_b = ebc.bus;
     }
-    if (cw & signal::OI) > 0 { // OUT Register In.
+    if cw.has(signal::OI) { // OUT Register In.
         ebc.reg_out = ebc.bus;
     }
-    if (cw & signal::J_) > 0 { // Program Counter In. Jump.
+    if cw.has(signal::J_) { // Program Counter In. Jump.
         ebc.pc = ebc.bus & 0b00001111;
+        ebc.pc_wrap = false;
+    }
+    if cw.has(signal::XI) { // Saved PC In.
+        ebc.epc = ebc.bus;
+    }
+
+    // Interrupt control. Acknowledging a request masks further ones
+    // until the handler re-enables them, usually with RTI.
+    if cw.has(signal::IA) {
+        ebc.int_cycle = true;
+        ebc.irq = false;
+        ebc.ie = false;
+    }
+    if cw.has(signal::DI) {
+        ebc.ie = false;
+    }
+    if cw.has(signal::EI) {
+        ebc.ie = true;
     }
     
     ebc.sc = (ebc.sc + 1) & 0b111; // Micro step counter
+    if ebc.sc == 0 {
+        ebc.int_cycle = false;
+    }
 
-    if (cw & signal::CE) > 0 { // Increment PC on 'Counter Enable' signal. 
-        ebc.pc = ebc.pc + 1;
+    if cw.has(signal::CE) { // Increment PC on 'Counter Enable' signal. 
+        ebc.pc = (ebc.pc + 1) & 0b00001111; // Only 4 bits wide.
+        if ebc.pc == 0 {
+            ebc.pc_wrap = true;
+        }
     }
 
-    if (cw & signal::HLT) > 0 {
+    if cw.has(signal::HLT) {
         ebc.hlt = true;
     }
-}
\ No newline at end of file
+}
+
+// ALU with the signed flags extension. Wraps like the real adder chips,
+// and latches all four flags on FI, so two's complement results work.
+fn update_alu_signed(ebc: &mut EBC, cw: ControlWord) {
+    let (a, b) = (ebc.reg_a, ebc.reg_b);
+    let ((val, carry), (_, overflow)) = if cw.has(signal::SU) {
+        (a.overflowing_sub(b), (a as i8).overflowing_sub(b as i8))
+    } else {
+        (a.overflowing_add(b), (a as i8).overflowing_add(b as i8))
+    };
+    ebc.reg_alu = val;
+
+    if cw.has(signal::FI) {
+        ebc.reg_flgs = 0;
+        if carry         { ebc.reg_flgs |= signal::CF; }
+        if val == 0      { ebc.reg_flgs |= signal::ZF; }
+        if val & 0x80 > 0 { ebc.reg_flgs |= signal::NF; }
+        if overflow      { ebc.reg_flgs |= signal::VF; }
+    }
+}
+
+// One clock tick - decode, then update - checked for errors. On an
+// error the tick is not run, so the machine shows where it went wrong.
+pub fn step(ebc: &mut EBC, ram: &mut [u8; 16]) -> Result<ControlWord, BeebcError> {
+    if ebc.sc == 0 && !ebc.int_cycle && !(ebc.irq && ebc.ie) && ebc.pc_wrap {
+        // About to fetch from address 0 again, without having jumped there.
+        // When trapping, the flag stays set, so trying again fails again,
+        // until a jump or a reset. A pending interrupt jumps to its handler
+        // instead.
+        match ebc.overflow {
+            Overflow::Trap => return Err(BeebcError::PcOverflow),
+            Overflow::Wrap => ebc.pc_wrap = false,
+            Overflow::Halt => {
+                update_modules(ebc, signal::HLT, ram);
+                return Ok(signal::HLT);
+            },
+        }
+    }
+    if ebc.sc == 2 && !ebc.int_cycle && ebc.undef == Undefined::Trap {
+        let op = ebc.ir >> 4;
+        if defined(ebc, op).is_err() {
+            // The MAR still holds the address the instruction was fetched from.
+            return Err(BeebcError::UndefinedOpcode { addr: ebc.mar, op });
+        }
+    }
+    let cw = decode_instruction(ebc);
+    if let Some(e) = ebc.protect.check(ebc, cw) {
+        if !ebc.protect.warn {
+            return Err(e);
+        }
+        ebc.breach = Some(e);
+    }
+    update_modules(ebc, cw, ram);
+    Ok(cw)
+}
+
+// Loads a program image, as written by the assembler, into ram.
+pub fn load(ram: &mut [u8; 16], image: &[u8]) -> Result<(), BeebcError> {
+    if image.len() != ram.len() {
+        return Err(BeebcError::ProgramSize { len: image.len(), expected: ram.len() });
+    }
+    ram.copy_from_slice(image);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ticks until the machine halts or fails, at most `max` times.
+    fn run(ebc: &mut EBC, ram: &mut [u8; 16], max: usize) -> Result<(), BeebcError> {
+        for _ in 0..max {
+            if ebc.hlt {
+                break;
+            }
+            step(ebc, ram)?;
+        }
+        Ok(())
+    }
+
+    fn ticks(ebc: &mut EBC, ram: &mut [u8; 16], n: usize) {
+        for _ in 0..n {
+            step(ebc, ram).unwrap();
+        }
+    }
+
+    // Adds (or subtracts) b from a with the flags latched, as ADD and SUB do.
+    fn alu(nv_flags: bool, a: u8, b: u8, sub: bool) -> (u8, u8) {
+        let mut ebc = EBC { nv_flags, reg_a: a, reg_b: b, ..EBC::default() };
+        let cw = if sub { signal::EO | signal::FI | signal::SU } else { signal::EO | signal::FI };
+        update_modules(&mut ebc, cw, &mut [0; 16]);
+        (ebc.reg_alu, ebc.reg_flgs)
+    }
+
+    // LDA 14, ADD or SUB 15, then `jump` to 5, where 9 is output. Falling
+    // through outputs what was worked out.
+    fn branch(jump: u8, sub: bool, a: u8, b: u8) -> [u8; 16] {
+        let op = if sub { 0x3F } else { 0x2F };
+        [0x1E, op, jump << 4 | 5, 0xE0, 0xF0, 0x59, 0xE0, 0xF0, 0, 0, 0, 0, 0, 0, a, b]
+    }
+
+    #[test]
+    fn runs_an_example() {
+        let mut ebc = EBC::default();
+        let mut ram = examples::ADD42;
+        run(&mut ebc, &mut ram, 1000).unwrap();
+        assert!(ebc.hlt);
+        assert_eq!(ebc.reg_out, 42);
+    }
+
+    #[test]
+    fn load_needs_a_whole_image() {
+        let mut ram = [0; 16];
+        assert_eq!(load(&mut ram, &[0x50; 4]), Err(BeebcError::ProgramSize { len: 4, expected: 16 }));
+        assert_eq!(ram, [0; 16]);
+        assert_eq!(load(&mut ram, &[0x50; 16]), Ok(()));
+        assert_eq!(ram, [0x50; 16]);
+    }
+
+    #[test]
+    fn pc_overflow_is_kept_until_a_jump() {
+        let mut ebc = EBC::default();
+        let mut ram = [0; 16]; // All NOP.
+        assert_eq!(run(&mut ebc, &mut ram, 1000), Err(BeebcError::PcOverflow));
+        assert_eq!((ebc.pc, ebc.sc), (0, 0));
+        // Trying again fails again, rather than fetching from 0.
+        assert_eq!(step(&mut ebc, &mut ram), Err(BeebcError::PcOverflow));
+        power::reset(&mut ebc);
+        assert!(step(&mut ebc, &mut ram).is_ok());
+    }
+
+    #[test]
+    fn pc_overflow_policies() {
+        let mut ram = [0; 16];
+        ram[1] = 0xE0; // OUT, every time round.
+        let mut ebc = EBC { overflow: Overflow::Wrap, ..EBC::default() };
+        run(&mut ebc, &mut ram, 1000).unwrap();
+        assert!(!ebc.hlt);
+        let mut ebc = EBC { overflow: Overflow::Halt, ..EBC::default() };
+        run(&mut ebc, &mut ram, 1000).unwrap();
+        assert!(ebc.hlt);
+        assert_eq!(ebc.pc, 0);
+    }
+
+    #[test]
+    fn signed_flags() {
+        use signal::{CF, ZF, NF, VF};
+        assert_eq!(alu(true, 100, 100, false), (200, NF | VF));
+        assert_eq!(alu(true, 200, 100, false), (44, CF));
+        assert_eq!(alu(true, 0x80, 1, true), (0x7F, VF));
+        assert_eq!(alu(true, 1, 2, true), (0xFF, CF | NF));
+        assert_eq!(alu(true, 5, 5, true), (0, ZF));
+        // Without the extension, only C or Z, and the ALU does not wrap.
+        assert_eq!(alu(false, 100, 100, false), (200, 0));
+        assert_eq!(alu(false, 5, 5, true), (0, ZF));
+        assert_eq!(alu(false, 1, 2, true).1, CF);
+    }
+
+    #[test]
+    fn jn_and_jv() {
+        let jn = Opcode::Jn as u8;
+        let jv = Opcode::Jv as u8;
+        for (ram, out) in [
+            (branch(jn, true, 1, 2), 9),
+            (branch(jn, true, 2, 1), 1),
+            (branch(jv, false, 100, 100), 9),
+            (branch(jv, false, 100, 20), 120),
+        ] {
+            let mut ebc = EBC { nv_flags: true, ..EBC::default() };
+            let mut ram = ram;
+            run(&mut ebc, &mut ram, 1000).unwrap();
+            assert!(ebc.hlt);
+            assert_eq!(ebc.reg_out, out);
+        }
+    }
+
+    #[test]
+    fn jn_and_jv_need_the_extension() {
+        for jump in [Opcode::Jn, Opcode::Jv] {
+            let mut ebc = EBC::default();
+            let mut ram = branch(jump as u8, true, 2, 1);
+            assert_eq!(run(&mut ebc, &mut ram, 1000), Err(BeebcError::UndefinedOpcode { addr: 2, op: jump as u8 }));
+            let mut ebc = EBC { undef: Undefined::Nop, ..EBC::default() };
+            run(&mut ebc, &mut ram, 1000).unwrap();
+            assert_eq!(ebc.reg_out, 1);
+        }
+    }
+
+    // EI, then LDI 5 and a jump back to it, forever. The handler, at 8,
+    // outputs A and returns.
+    fn interrupted() -> (EBC, [u8; 16]) {
+        let mut ram = [0; 16];
+        ram[..3].copy_from_slice(&[0xA0, 0x55, 0x61]);
+        ram[8..10].copy_from_slice(&[0xE0, 0x90]);
+        (EBC { ivec: 8, ..EBC::default() }, ram)
+    }
+
+    #[test]
+    fn interrupts_are_taken_and_returned_from() {
+        let (mut ebc, mut ram) = interrupted();
+        ticks(&mut ebc, &mut ram, 8);
+        assert!(ebc.ie);
+        // Raised part way through LDI 5, and taken once it is done.
+        ticks(&mut ebc, &mut ram, 3);
+        ebc.irq = true;
+        ticks(&mut ebc, &mut ram, 5);
+        assert_eq!((ebc.pc, ebc.sc, ebc.reg_a), (2, 0, 5));
+        assert!(ebc.irq && !ebc.int_cycle);
+        ticks(&mut ebc, &mut ram, 8);
+        assert_eq!((ebc.pc, ebc.epc), (8, 2));
+        assert!(!ebc.irq && !ebc.ie);
+        // OUT, then RTI back to the jump, with interrupts enabled again.
+        ticks(&mut ebc, &mut ram, 16);
+        assert_eq!(ebc.reg_out, 5);
+        assert_eq!(ebc.pc, 2);
+        assert!(ebc.ie);
+    }
+
+    #[test]
+    fn requests_wait_until_enabled() {
+        let (mut ebc, mut ram) = interrupted();
+        ebc.irq = true;
+        // Not taken before EI, nor at the next boundary straight after it
+        // if it is disabled again.
+        ticks(&mut ebc, &mut ram, 2);
+        assert_eq!(ebc.pc, 1);
+        ticks(&mut ebc, &mut ram, 6);
+        ebc.ie = false;
+        ticks(&mut ebc, &mut ram, 16);
+        assert_eq!(ebc.pc, 1);
+        assert!(ebc.irq);
+        // The request stays latched until interrupts are enabled.
+        ebc.ie = true;
+        ticks(&mut ebc, &mut ram, 8);
+        assert_eq!((ebc.pc, ebc.epc), (8, 1));
+        assert!(!ebc.irq);
+    }
+
+    #[test]
+    fn interrupt_at_the_wrap_point_is_taken() {
+        let mut ebc = EBC::default();
+        let mut ram = [0; 16];
+        ram[0] = 0xA0; // EI, then NOPs to the end of ram.
+        assert_eq!(run(&mut ebc, &mut ram, 1000), Err(BeebcError::PcOverflow));
+        ebc.irq = true;
+        ebc.ivec = 5;
+        for _ in 0..8 {
+            step(&mut ebc, &mut ram).unwrap();
+        }
+        assert_eq!(ebc.pc, 5);
+        assert!(!ebc.pc_wrap);
+    }
+}
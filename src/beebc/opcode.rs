@@ -1,11 +1,153 @@
-pub const NOP: u8 = 0b0000; 
-pub const LDA: u8 = 0b0001; 
-pub const ADD: u8 = 0b0010;
-pub const SUB: u8 = 0b0011;
-pub const STA: u8 = 0b0100;
-pub const LDI: u8 = 0b0101;
-pub const JMP: u8 = 0b0110;
-pub const JC : u8 = 0b0111; 
-pub const JZ : u8 = 0b1000;
-pub const OUT: u8 = 0b1110;
-pub const HLT: u8 = 0b1111; 
\ No newline at end of file
+use core::fmt;
+use core::str::FromStr;
+use crate::beebc::signal::{self, ControlWord, NONE};
+
+// Op codes live in the top 4 bits of an instruction.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Nop = 0b0000,
+    Lda = 0b0001,
+    Add = 0b0010,
+    Sub = 0b0011,
+    Sta = 0b0100,
+    Ldi = 0b0101,
+    Jmp = 0b0110,
+    Jc  = 0b0111,
+    Jz  = 0b1000,
+    Rti = 0b1001,
+    Ei  = 0b1010,
+    Di  = 0b1011,
+    Jn  = 0b1100,
+    Jv  = 0b1101,
+    Out = 0b1110,
+    Hlt = 0b1111,
+}
+
+// Everything the tools know about one instruction.
+pub struct Instruction {
+    pub op:       Opcode,
+    pub mnemonic: &'static str,
+    pub operands: u8,
+    pub flag:     u8,  // Microcode only runs if this flag is set. 0 - always.
+    pub nv_flags: bool, // Only defined with the signed flags extension.
+    pub uc:       [ControlWord; 6],  // Micro code, after the 2 fetch steps.
+}
+
+// The instruction set. The assembler, decoder and gui all read this table.
+pub const ISA: [Instruction; 16] = [
+    Instruction { op: Opcode::Nop, mnemonic: "NOP", operands: 0, flag: 0, nv_flags: false,
+        uc: [NONE, NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Lda, mnemonic: "LDA", operands: 1, flag: 0, nv_flags: false,
+        uc: [signal::IO.with(signal::MI),
+             signal::RO.with(signal::AI),
+             NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Add, mnemonic: "ADD", operands: 1, flag: 0, nv_flags: false,
+        uc: [signal::IO.with(signal::MI),
+             signal::RO.with(signal::BI),
+             signal::EO.with(signal::AI).with(signal::FI),
+             NONE, NONE, NONE] },
+    Instruction { op: Opcode::Sub, mnemonic: "SUB", operands: 1, flag: 0, nv_flags: false,
+        uc: [signal::IO.with(signal::MI),
+             signal::RO.with(signal::BI),
+             signal::EO.with(signal::AI).with(signal::SU).with(signal::FI),
+             NONE, NONE, NONE] },
+    Instruction { op: Opcode::Sta, mnemonic: "STA", operands: 1, flag: 0, nv_flags: false,
+        uc: [signal::IO.with(signal::MI),
+             signal::AO.with(signal::RI),
+             NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Ldi, mnemonic: "LDI", operands: 1, flag: 0, nv_flags: false,
+        uc: [signal::IO.with(signal::AI),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Jmp, mnemonic: "JMP", operands: 1, flag: 0, nv_flags: false,
+        uc: [signal::IO.with(signal::J_),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Jc,  mnemonic: "JC",  operands: 1, flag: signal::CF, nv_flags: false,
+        uc: [signal::IO.with(signal::J_),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Jz,  mnemonic: "JZ",  operands: 1, flag: signal::ZF, nv_flags: false,
+        uc: [signal::IO.with(signal::J_),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Rti, mnemonic: "RTI", operands: 0, flag: 0, nv_flags: false,
+        uc: [signal::XO.with(signal::J_).with(signal::EI),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Ei,  mnemonic: "EI",  operands: 0, flag: 0, nv_flags: false,
+        uc: [signal::EI, NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Di,  mnemonic: "DI",  operands: 0, flag: 0, nv_flags: false,
+        uc: [signal::DI, NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Jn,  mnemonic: "JN",  operands: 1, flag: signal::NF, nv_flags: true,
+        uc: [signal::IO.with(signal::J_),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Jv,  mnemonic: "JV",  operands: 1, flag: signal::VF, nv_flags: true,
+        uc: [signal::IO.with(signal::J_),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Out, mnemonic: "OUT", operands: 0, flag: 0, nv_flags: false,
+        uc: [signal::AO.with(signal::OI),
+             NONE, NONE, NONE, NONE, NONE] },
+    Instruction { op: Opcode::Hlt, mnemonic: "HLT", operands: 0, flag: 0, nv_flags: false,
+        uc: [signal::HLT, NONE, NONE, NONE, NONE, NONE] },
+];
+
+// Steps every instruction starts with, to fetch it, before its micro code.
+pub const FETCH_STEPS: usize = 2;
+
+impl Instruction {
+    // Whether the operand is an address - of data in ram, or to jump to -
+    // rather than a value.
+    pub fn addresses(&self) -> bool {
+        self.operands > 0 && (self.uc[0].has(signal::MI) || self.uc[0].has(signal::J_))
+    }
+
+    // Clock ticks it takes. Always the whole micro code, used or not.
+    pub fn cycles(&self) -> usize {
+        FETCH_STEPS + self.uc.len()
+    }
+
+    // Ticks up to the last one that asserts a signal - what it would take
+    // if steps with nothing left to do were skipped. Conditional
+    // instructions only get past the fetch if their flag is set.
+    pub fn busy(&self) -> usize {
+        FETCH_STEPS + self.uc.iter().rposition(|cw| *cw != NONE).map_or(0, |i| i + 1)
+    }
+}
+
+impl Opcode {
+    pub fn info(self) -> &'static Instruction {
+        ISA.iter().find(|i| i.op == self).expect("every opcode is in the ISA table")
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        self.info().mnemonic
+    }
+
+    pub fn operands(self) -> u8 {
+        self.info().operands
+    }
+}
+
+// Decodes a 4 bit op code. Unused op codes are handed back as the error.
+impl TryFrom<u8> for Opcode {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Opcode, u8> {
+        ISA.iter().find(|i| i.op as u8 == v).map(|i| i.op).ok_or(v)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownMnemonic;
+
+// Mnemonics are not case sensitive - LDA, lda and Lda are all the same.
+impl FromStr for Opcode {
+    type Err = UnknownMnemonic;
+
+    fn from_str(s: &str) -> Result<Opcode, UnknownMnemonic> {
+        ISA.iter().find(|i| i.mnemonic.eq_ignore_ascii_case(s)).map(|i| i.op).ok_or(UnknownMnemonic)
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.mnemonic())
+    }
+}
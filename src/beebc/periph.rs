@@ -0,0 +1,73 @@
+use crate::beebc::EBC;
+
+// Anything attached to the machine that is clocked alongside it.
+pub trait Peripheral {
+    // Called once per clock tick, after the modules have been updated.
+    fn tick(&mut self, ebc: &mut EBC);
+
+    // The peripheral's internal state, for spotting machines stuck in a
+    // loop. None if it cannot be summed up, which turns detection off.
+    fn state(&self) -> Option<u64> {
+        None
+    }
+}
+
+// Programmable interval timer. Raises the interrupt request line
+// once every `period` clock ticks. A period of 0 disables the timer.
+#[derive(Default, Debug)]
+pub struct Timer {
+    pub period: u32,
+    pub count:  u32,
+}
+
+impl Timer {
+    pub fn new(period: u32) -> Timer {
+        Timer { period, count: 0 }
+    }
+}
+
+impl Peripheral for Timer {
+    fn tick(&mut self, ebc: &mut EBC) {
+        if self.period == 0 { return; }
+        self.count += 1;
+        if self.count >= self.period {
+            self.count = 0;
+            ebc.irq = true;
+        }
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.count as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_raises_requests() {
+        let mut ebc = EBC::default();
+        let mut timer = Timer::new(3);
+        let raised: Vec<bool> = (0..6).map(|_| {
+            ebc.irq = false;
+            timer.tick(&mut ebc);
+            ebc.irq
+        }).collect();
+        assert_eq!(raised, [false, false, true, false, false, true]);
+        // A request stays raised until the machine takes it.
+        ebc.irq = true;
+        timer.tick(&mut ebc);
+        assert!(ebc.irq);
+    }
+
+    #[test]
+    fn period_0_is_off() {
+        let mut ebc = EBC::default();
+        let mut timer = Timer::new(0);
+        for _ in 0..10 {
+            timer.tick(&mut ebc);
+        }
+        assert!(!ebc.irq);
+    }
+}
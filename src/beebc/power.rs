@@ -0,0 +1,105 @@
+use crate::beebc::EBC;
+use crate::beebc::signal;
+
+// Small seedable random number generator (SplitMix64). Good enough for
+// garbage in registers, and needs nothing from std.
+pub struct SplitMix(u64);
+
+impl SplitMix {
+    pub fn new(seed: u64) -> SplitMix {
+        SplitMix(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+// The physical reset button. Clears every register, but leaves ram, and
+// how the machine is configured, alone.
+pub fn reset(ebc: &mut EBC) {
+    *ebc = EBC {
+        ivec:     ebc.ivec,
+        in_addr:  ebc.in_addr,
+        undef:    ebc.undef,
+        overflow: ebc.overflow,
+        nv_flags: ebc.nv_flags,
+        protect:  ebc.protect,
+        ..EBC::default()
+    };
+}
+
+// Power-on state of the breadboard - registers, and every ram cell not in
+// `keep` (bit n set keeps address n), hold whatever they came up with.
+// The program and step counters still start at 0, as they would after
+// the power-on reset pulse, and interrupts start disabled.
+pub fn power_on(ebc: &mut EBC, ram: &mut [u8; 16], keep: u16, seed: u64) {
+    let mut rng = SplitMix::new(seed);
+    reset(ebc);
+
+    ebc.mar     = rng.next_u8() & 0b1111;
+    ebc.ir      = rng.next_u8();
+    ebc.reg_a   = rng.next_u8();
+    ebc.reg_b   = rng.next_u8();
+    ebc.reg_alu = rng.next_u8();
+    ebc.reg_out = rng.next_u8();
+    ebc.reg_in  = rng.next_u8();
+    ebc.bus     = rng.next_u8();
+    ebc.epc     = rng.next_u8() & 0b1111;
+    let flags = if ebc.nv_flags {
+        signal::CF | signal::ZF | signal::NF | signal::VF
+    } else {
+        signal::CF | signal::ZF
+    };
+    ebc.reg_flgs = rng.next_u8() & flags;
+
+    for (addr, cell) in ram.iter_mut().enumerate() {
+        if keep & (1 << addr) == 0 {
+            *cell = rng.next_u8();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beebc::error::Undefined;
+
+    #[test]
+    fn reset_keeps_ram_and_settings() {
+        let mut ebc = EBC { pc: 5, reg_a: 9, hlt: true, ivec: 3, nv_flags: true, undef: Undefined::Halt, ..EBC::default() };
+        reset(&mut ebc);
+        assert_eq!(ebc, EBC { ivec: 3, nv_flags: true, undef: Undefined::Halt, ..EBC::default() });
+    }
+
+    #[test]
+    fn power_on_is_repeatable() {
+        let (mut a, mut b) = (EBC::default(), EBC::default());
+        let (mut ram_a, mut ram_b) = ([0; 16], [0; 16]);
+        power_on(&mut a, &mut ram_a, 0, 42);
+        power_on(&mut b, &mut ram_b, 0, 42);
+        assert_eq!((&a, ram_a), (&b, ram_b));
+        power_on(&mut b, &mut ram_b, 0, 43);
+        assert_ne!(ram_a, ram_b);
+    }
+
+    #[test]
+    fn power_on_keeps_the_program() {
+        let mut ebc = EBC::default();
+        let mut ram = [0; 16];
+        power_on(&mut ebc, &mut ram, 0b0000_0000_0000_0111, 7);
+        assert_eq!(ram[..3], [0, 0, 0]);
+        assert!(ram[3..].iter().any(|c| *c != 0));
+        assert_eq!((ebc.pc, ebc.sc, ebc.ie, ebc.hlt), (0, 0, false, false));
+        assert!(ebc.mar < 16 && ebc.epc < 16);
+        assert_eq!(ebc.reg_flgs & (signal::NF | signal::VF), 0);
+    }
+}
@@ -0,0 +1,135 @@
+use core::fmt;
+use crate::beebc::EBC;
+use crate::beebc::opcode::Opcode;
+use crate::beebc::signal::{self, ControlWord};
+
+// Signals that drive the bus. A tick with none of them leaves it idle.
+const BUS_WRITERS: ControlWord = signal::RO.with(signal::IO).with(signal::AO)
+    .with(signal::CO).with(signal::XO).with(signal::VO).with(signal::EO);
+
+// Performance counters for one machine, over one run.
+#[derive(Default, Debug, Clone)]
+pub struct Profile {
+    pub cycles:      u64,        // Clock ticks.
+    pub retired:     u64,        // Instructions executed.
+    pub interrupts:  u64,        // Interrupts serviced.
+    pub bus_idle:    u64,        // Ticks where nothing drove the bus.
+    pub op_counts:   [u64; 16],  // Instructions executed, by op code.
+    pub exec_counts: [u64; 16],  // Instructions fetched, by address.
+    pub ram_reads:   [u64; 16],  // Data reads, by address. Fetches not included.
+    pub input_reads: u64,        // Data reads of the input port, mapped over ram.
+    pub ram_writes:  [u64; 16],  // Writes, by address.
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    // Records one tick, given the state of the machine before it and the
+    // control word it ran.
+    pub fn record(&mut self, before: &EBC, cw: ControlWord) {
+        self.cycles += 1;
+        if (cw.0 & BUS_WRITERS.0) == 0 {
+            self.bus_idle += 1;
+        }
+        if cw.has(signal::IA) {
+            self.interrupts += 1;
+        }
+
+        // Ticks of an ordinary instruction cycle, not an interrupt's.
+        let normal = !before.int_cycle && !cw.has(signal::IA);
+        if normal && before.sc == 0 {
+            self.exec_counts[before.pc as usize & 0b1111] += 1;
+        }
+        if normal && before.sc == 2 {
+            self.retired += 1;
+            self.op_counts[(before.ir >> 4) as usize] += 1;
+        }
+
+        let mar = before.mar as usize & 0b1111;
+        if cw.has(signal::RO) && !(normal && before.sc == 1) {
+            if before.in_addr == Some(before.mar) {
+                self.input_reads += 1;
+            } else {
+                self.ram_reads[mar] += 1;
+            }
+        }
+        if cw.has(signal::RI) {
+            self.ram_writes[mar] += 1;
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pct = |n: u64| if self.cycles > 0 { 100.0 * n as f64 / self.cycles as f64 } else { 0.0 };
+        let cpi = if self.retired > 0 { self.cycles as f64 / self.retired as f64 } else { 0.0 };
+
+        writeln!(f, "cycles        {}", self.cycles)?;
+        writeln!(f, "instructions  {} ({:.1} cycles each)", self.retired, cpi)?;
+        writeln!(f, "interrupts    {}", self.interrupts)?;
+        writeln!(f, "bus idle      {} ({:.1}%)", self.bus_idle, pct(self.bus_idle))?;
+        writeln!(f, "input reads   {}", self.input_reads)?;
+
+        writeln!(f, "op    count")?;
+        for (op, n) in self.op_counts.iter().enumerate() {
+            if *n == 0 { continue; }
+            match Opcode::try_from(op as u8) {
+                Ok(op) => writeln!(f, "{:<4}  {}", op, n)?,
+                Err(_) => writeln!(f, "{:04b}  {}", op, n)?,
+            }
+        }
+
+        writeln!(f, "addr  exec  reads  writes")?;
+        for a in 0..16 {
+            let (e, r, w) = (self.exec_counts[a], self.ram_reads[a], self.ram_writes[a]);
+            if e + r + w == 0 { continue; }
+            writeln!(f, "{:04b}  {:<4}  {:<5}  {}", a, e, r, w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beebc;
+
+    // Runs a program to the end, recording every tick.
+    fn profile(ram: &mut [u8; 16], in_addr: Option<u8>) -> Profile {
+        let mut ebc = EBC { in_addr, ..EBC::default() };
+        let mut p = Profile::new();
+        while !ebc.hlt {
+            let before = ebc.clone();
+            let cw = beebc::step(&mut ebc, ram).unwrap();
+            p.record(&before, cw);
+        }
+        p
+    }
+
+    #[test]
+    fn counts() {
+        // LDA 14, ADD 15, STA 13, HLT.
+        let mut ram = [0x1E, 0x2F, 0x4D, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2];
+        let p = profile(&mut ram, None);
+        assert_eq!((p.cycles, p.retired, p.interrupts), (3 * 8 + 3, 4, 0));
+        assert_eq!(p.op_counts[Opcode::Lda as usize], 1);
+        assert_eq!(p.op_counts[Opcode::Hlt as usize], 1);
+        assert_eq!(p.exec_counts[..4], [1, 1, 1, 1]);
+        assert_eq!(p.exec_counts[4], 0);
+        assert_eq!((p.ram_reads[14], p.ram_reads[15], p.ram_reads[0]), (1, 1, 0));
+        assert_eq!(p.ram_writes[13], 1);
+        // Each instruction drives the bus for its 2 fetch ticks, plus LDA's,
+        // ADD's and STA's 2, 3 and 2. HLT stops on its first.
+        assert_eq!(p.bus_idle, p.cycles - 8 - 7);
+    }
+
+    #[test]
+    fn input_port_reads_are_not_ram_reads() {
+        let mut ram = [0x1E, 0x2F, 0x4D, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2];
+        let p = profile(&mut ram, Some(15));
+        assert_eq!(p.input_reads, 1);
+        assert_eq!((p.ram_reads[14], p.ram_reads[15]), (1, 0));
+    }
+}
@@ -0,0 +1,80 @@
+use crate::beebc::EBC;
+use crate::beebc::error::{Access, BeebcError};
+use crate::beebc::signal::{self, ControlWord};
+
+// Access rules for ram, a bit per address in each mask.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    pub read_only: u16, // Never written.
+    pub exec_only: u16, // Only fetched - never read as data, or written.
+    pub no_exec:   u16, // Never fetched.
+    pub warn:      bool, // Report breaches, instead of stopping on them.
+}
+
+impl Protection {
+    // The rule the tick about to run with `cw` would break, if any.
+    pub fn check(&self, ebc: &EBC, cw: ControlWord) -> Option<BeebcError> {
+        if ebc.int_cycle {
+            return None;
+        }
+        let addr = ebc.mar & 0b1111;
+        let bit = 1 << addr;
+        // Past the fetch, the PC is one on from the instruction.
+        let pc = ebc.pc.wrapping_sub(1) & 0b1111;
+        let fetch = ebc.sc == 1;
+
+        if cw.has(signal::RI) && (self.read_only | self.exec_only) & bit != 0 {
+            return Some(BeebcError::Protected { addr, pc, access: Access::Write });
+        }
+        if cw.has(signal::RO) && ebc.in_addr != Some(ebc.mar) {
+            if fetch && self.no_exec & bit != 0 {
+                return Some(BeebcError::Protected { addr, pc: addr, access: Access::Execute });
+            }
+            if !fetch && self.exec_only & bit != 0 {
+                return Some(BeebcError::Protected { addr, pc, access: Access::Read });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(sc: u8, mar: u8) -> EBC {
+        EBC { sc, mar, pc: mar.wrapping_add(1), ..EBC::default() }
+    }
+
+    #[test]
+    fn read_only() {
+        let p = Protection { read_only: 1 << 3, ..Protection::default() };
+        assert!(matches!(p.check(&at(3, 3), signal::RI), Some(BeebcError::Protected { addr: 3, access: Access::Write, .. })));
+        assert_eq!(p.check(&at(3, 4), signal::RI), None);
+        assert_eq!(p.check(&at(3, 3), signal::RO), None);
+    }
+
+    #[test]
+    fn exec_only() {
+        let p = Protection { exec_only: 1 << 5, ..Protection::default() };
+        assert_eq!(p.check(&at(1, 5), signal::RO), None);
+        assert!(matches!(p.check(&at(3, 5), signal::RO), Some(BeebcError::Protected { access: Access::Read, .. })));
+        assert!(matches!(p.check(&at(3, 5), signal::RI), Some(BeebcError::Protected { access: Access::Write, .. })));
+    }
+
+    #[test]
+    fn no_exec() {
+        let p = Protection { no_exec: 1 << 15, ..Protection::default() };
+        assert!(matches!(p.check(&at(1, 15), signal::RO), Some(BeebcError::Protected { addr: 15, pc: 15, access: Access::Execute })));
+        assert_eq!(p.check(&at(3, 15), signal::RO), None);
+    }
+
+    #[test]
+    fn input_port_and_interrupts_are_not_checked() {
+        let p = Protection { exec_only: 1 << 15, ..Protection::default() };
+        let ebc = EBC { in_addr: Some(15), ..at(3, 15) };
+        assert_eq!(p.check(&ebc, signal::RO), None);
+        let ebc = EBC { int_cycle: true, ..at(3, 15) };
+        assert_eq!(p.check(&ebc, signal::RO), None);
+    }
+}
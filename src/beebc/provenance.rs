@@ -0,0 +1,332 @@
+use crate::beebc::EBC;
+use crate::beebc::opcode::Opcode;
+use crate::beebc::signal::{self, ControlWord};
+use std::fmt::Write;
+use std::rc::Rc;
+
+// History deeper than this is summed up instead of kept, so a value that
+// a loop keeps adding to does not drag its whole past along.
+const MAX_DEPTH: u32 = 32;
+
+// Where something in the machine got its value.
+pub type Tag = Option<Rc<Value>>;
+
+#[derive(Debug)]
+pub enum Origin {
+    Start(u8),                 // In ram at this address when the machine started.
+    Input,                     // Read from the input port.
+    Alu { sub: bool, a: Tag, b: Tag },
+    Latch { into: Place, from: Tag },
+    Elided,                    // Older history, summed up by the source sets.
+}
+
+// The registers and cells data is latched into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Place {
+    A,
+    B,
+    Out,
+    Ram(u8),
+}
+
+#[derive(Debug)]
+pub struct Value {
+    pub origin: Origin,
+    pub value:  u8,
+    pub cycle:  u64,
+    pub pc:     u8,   // Address of the instruction that made it.
+    pub op:     u8,   // And its op code.
+    pub addrs:  u16,  // Ram cells whose starting values it depends on.
+    pub insts:  u16,  // Addresses of the instructions it passed through.
+    depth:      u32,
+}
+
+// Data-flow tracking for one machine. Tags ram, the data registers and the
+// bus with where their values came from, following them across the bus
+// every tick.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    ram:     [Tag; 16],
+    a:       Tag,
+    b:       Tag,
+    out:     Tag,
+    ir:      Tag,
+    bus:     Tag,
+    cycles:  u64,
+    inst_pc: u8, // Address of the instruction being executed.
+}
+
+impl Provenance {
+    pub fn new(ram: &[u8; 16]) -> Provenance {
+        let mut tags: [Tag; 16] = Default::default();
+        for (addr, tag) in tags.iter_mut().enumerate() {
+            *tag = Some(Rc::new(Value {
+                origin: Origin::Start(addr as u8),
+                value:  ram[addr],
+                cycle:  0,
+                pc:     0,
+                op:     0,
+                addrs:  1 << addr,
+                insts:  0,
+                depth:  0,
+            }));
+        }
+        Provenance { ram: tags, a: None, b: None, out: None, ir: None, bus: None, cycles: 0, inst_pc: 0 }
+    }
+
+    // Where the value in the output register came from.
+    pub fn out(&self) -> &Tag {
+        &self.out
+    }
+
+    // Follows one tick, given the state of the machine before and after it
+    // and the control word it ran.
+    pub fn record(&mut self, before: &EBC, after: &EBC, cw: ControlWord) {
+        self.cycles += 1;
+        if before.sc == 0 {
+            self.inst_pc = before.pc & 0b1111;
+        }
+        let mar = before.mar & 0b1111;
+
+        self.bus = if cw.has(signal::RO) {
+            if before.in_addr == Some(before.mar) {
+                Some(self.value(Origin::Input, after.bus, before, 0, 0))
+            } else {
+                self.ram[mar as usize].clone()
+            }
+        } else if cw.has(signal::IO) {
+            self.ir.clone()
+        } else if cw.has(signal::AO) {
+            self.a.clone()
+        } else if cw.has(signal::EO) {
+            let (a, b) = (trim(&self.a), trim(&self.b));
+            let (addrs, insts) = (sources(&a).0 | sources(&b).0, sources(&a).1 | sources(&b).1);
+            let alu = Origin::Alu { sub: cw.has(signal::SU), a, b };
+            Some(self.value(alu, after.bus, before, addrs, insts))
+        } else {
+            None // The PC, or nothing - not data.
+        };
+
+        if cw.has(signal::II) {
+            self.ir = self.bus.clone();
+        }
+        if cw.has(signal::AI) {
+            self.a = self.latch(Place::A, after.reg_a, before);
+        }
+        if cw.has(signal::BI) {
+            self.b = self.latch(Place::B, after.reg_b, before);
+        }
+        if cw.has(signal::OI) {
+            self.out = self.latch(Place::Out, after.reg_out, before);
+        }
+        if cw.has(signal::RI) {
+            self.ram[mar as usize] = self.latch(Place::Ram(mar), after.bus, before);
+        }
+    }
+
+    fn latch(&self, into: Place, value: u8, before: &EBC) -> Tag {
+        let from = trim(&self.bus);
+        let (addrs, insts) = sources(&from);
+        Some(self.value(Origin::Latch { into, from }, value, before, addrs, insts))
+    }
+
+    fn value(&self, origin: Origin, value: u8, before: &EBC, addrs: u16, insts: u16) -> Rc<Value> {
+        let depth = match &origin {
+            Origin::Alu { a, b, .. } => depth(a).max(depth(b)) + 1,
+            Origin::Latch { from, .. } => depth(from) + 1,
+            _ => 0,
+        };
+        Rc::new(Value {
+            origin,
+            value,
+            cycle: self.cycles,
+            pc:    self.inst_pc,
+            op:    before.ir >> 4,
+            addrs,
+            insts: insts | 1 << self.inst_pc,
+            depth,
+        })
+    }
+}
+
+fn sources(tag: &Tag) -> (u16, u16) {
+    tag.as_ref().map_or((0, 0), |v| (v.addrs, v.insts))
+}
+
+fn depth(tag: &Tag) -> u32 {
+    tag.as_ref().map_or(0, |v| v.depth)
+}
+
+// The tag itself, or a summary of it if its history is too long to keep.
+fn trim(tag: &Tag) -> Tag {
+    match tag {
+        Some(v) if v.depth >= MAX_DEPTH => Some(Rc::new(Value {
+            origin: Origin::Elided,
+            value:  v.value,
+            cycle:  v.cycle,
+            pc:     v.pc,
+            op:     v.op,
+            addrs:  v.addrs,
+            insts:  v.insts,
+            depth:  0,
+        })),
+        _ => tag.clone(),
+    }
+}
+
+// Explains a value as the chain of loads, adds and stores that made it,
+// newest first, followed by every ram cell and instruction involved.
+pub fn explain(tag: &Tag) -> String {
+    let mut text = String::new();
+    walk(tag, 0, &mut Vec::new(), &mut text);
+    let (addrs, insts) = sources(tag);
+    let _ = writeln!(text, "from ram: {}", addr_list(addrs));
+    let _ = writeln!(text, "through instructions: {}", addr_list(insts));
+    text
+}
+
+// Computed values met more than once are only explained the first time.
+fn walk(tag: &Tag, indent: usize, seen: &mut Vec<*const Value>, text: &mut String) {
+    let pad = "  ".repeat(indent);
+    let v = match tag {
+        Some(v) => v,
+        None => {
+            let _ = writeln!(text, "{}(not from data)", pad);
+            return;
+        }
+    };
+    let computed = matches!(v.origin, Origin::Alu { .. } | Origin::Latch { .. });
+    if computed && seen.contains(&Rc::as_ptr(v)) {
+        let _ = writeln!(text, "{}{} (as above)", pad, v.value);
+        return;
+    }
+    seen.push(Rc::as_ptr(v));
+    let at = match Opcode::try_from(v.op) {
+        Ok(op) => format!("{} at {:04b}, cycle {}", op, v.pc, v.cycle),
+        Err(_) => format!("{:04b} at {:04b}, cycle {}", v.op, v.pc, v.cycle),
+    };
+    match &v.origin {
+        Origin::Start(addr) => {
+            let _ = writeln!(text, "{}{} in ram {:04b} from the start", pad, v.value, addr);
+        },
+        Origin::Input => {
+            let _ = writeln!(text, "{}{} from the input port, {}", pad, v.value, at);
+        },
+        Origin::Alu { sub, a, b } => {
+            let (x, y) = (a.as_ref().map_or(0, |a| a.value), b.as_ref().map_or(0, |b| b.value));
+            let sign = if *sub { '-' } else { '+' };
+            let _ = writeln!(text, "{}{} = {} {} {}, {}", pad, v.value, x, sign, y, at);
+            walk(a, indent + 1, seen, text);
+            walk(b, indent + 1, seen, text);
+        },
+        Origin::Latch { into, from } => {
+            let place = match into {
+                Place::A => "A".to_string(),
+                Place::B => "B".to_string(),
+                Place::Out => "OUT".to_string(),
+                Place::Ram(addr) => format!("ram {:04b}", addr),
+            };
+            let _ = writeln!(text, "{}{} into {}, {}", pad, v.value, place, at);
+            walk(from, indent + 1, seen, text);
+        },
+        Origin::Elided => {
+            let _ = writeln!(text, "{}{} ... (earlier history not kept)", pad, v.value);
+        },
+    }
+}
+
+fn addr_list(set: u16) -> String {
+    let addrs: Vec<String> = (0..16).filter(|a| set & (1 << a) != 0).map(|a| format!("{:04b}", a)).collect();
+    if addrs.is_empty() { "none".to_string() } else { addrs.join(" ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beebc;
+
+    // Runs a program until it halts, following its data, and gives where
+    // its last output came from.
+    fn traced(mut ram: [u8; 16], ebc: EBC) -> Tag {
+        let mut ebc = ebc;
+        let mut p = Provenance::new(&ram);
+        for _ in 0..10_000 {
+            if ebc.hlt {
+                break;
+            }
+            let before = ebc.clone();
+            let cw = beebc::step(&mut ebc, &mut ram).unwrap();
+            p.record(&before, &ebc, cw);
+        }
+        p.out().clone()
+    }
+
+    fn program(code: &[u8], data: &[(usize, u8)]) -> [u8; 16] {
+        let mut ram = [0; 16];
+        ram[..code.len()].copy_from_slice(code);
+        for (addr, v) in data {
+            ram[*addr] = *v;
+        }
+        ram
+    }
+
+    #[test]
+    fn follows_an_add() {
+        // LDA 14, ADD 15, OUT, HLT.
+        let out = traced(program(&[0x1E, 0x2F, 0xE0, 0xF0], &[(14, 3), (15, 4)]), EBC::default());
+        let v = out.as_ref().unwrap();
+        assert_eq!(v.value, 7);
+        assert!(matches!(v.origin, Origin::Latch { into: Place::Out, .. }));
+        assert_eq!(v.addrs, 1 << 14 | 1 << 15);
+        assert_eq!(v.insts, 0b111);
+        let text = explain(&out);
+        assert!(text.starts_with("7 into OUT, OUT at 0010"), "{}", text);
+        assert!(text.contains("7 = 3 + 4, ADD at 0001"), "{}", text);
+        assert!(text.contains("3 in ram 1110 from the start"), "{}", text);
+        assert!(text.ends_with("from ram: 1110 1111\nthrough instructions: 0000 0001 0010\n"), "{}", text);
+    }
+
+    #[test]
+    fn follows_values_through_ram() {
+        // LDA 14, STA 13, LDI 0, LDA 13, OUT, HLT.
+        let out = traced(program(&[0x1E, 0x4D, 0x50, 0x1D, 0xE0, 0xF0], &[(14, 9)]), EBC::default());
+        let v = out.as_ref().unwrap();
+        assert_eq!(v.value, 9);
+        assert_eq!(v.addrs, 1 << 14);
+        assert_eq!(v.insts, 0b11011);
+        assert!(explain(&out).contains("9 into ram 1101, STA at 0001"));
+    }
+
+    #[test]
+    fn immediates_come_from_the_instruction() {
+        // LDI 5, OUT, HLT.
+        let out = traced(program(&[0x55, 0xE0, 0xF0], &[]), EBC::default());
+        assert_eq!(out.as_ref().unwrap().addrs, 1 << 0);
+    }
+
+    #[test]
+    fn input_port() {
+        // LDA 15, OUT, HLT, with the input port over 15.
+        let ebc = EBC { in_addr: Some(15), reg_in: 6, ..EBC::default() };
+        let out = traced(program(&[0x1F, 0xE0, 0xF0], &[]), ebc);
+        assert_eq!(out.as_ref().unwrap().addrs, 0);
+        assert!(explain(&out).contains("6 from the input port, LDA at 0000"));
+    }
+
+    #[test]
+    fn long_histories_are_cut_short() {
+        // ADD 15, JMP 0, 40 times round.
+        let mut ram = program(&[0x2F, 0x60], &[(15, 1)]);
+        let mut ebc = EBC::default();
+        let mut p = Provenance::new(&ram);
+        for _ in 0..40 * 16 {
+            let before = ebc.clone();
+            let cw = beebc::step(&mut ebc, &mut ram).unwrap();
+            p.record(&before, &ebc, cw);
+        }
+        assert!(depth(&p.a) <= MAX_DEPTH + 2);
+        let text = explain(&p.a);
+        assert!(text.starts_with("40 into A"), "{}", text);
+        assert!(text.contains("(earlier history not kept)"));
+    }
+}
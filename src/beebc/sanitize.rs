@@ -0,0 +1,174 @@
+use core::fmt;
+use crate::beebc::EBC;
+use crate::beebc::signal::{self, ControlWord};
+
+// Shadow bits kept for each ram cell.
+const INIT:    u8 = 0b0001; // Holds a value put there by the program image or STA.
+const WRITTEN: u8 = 0b0010; // Last written by STA - data.
+const READ:    u8 = 0b0100; // Read as data.
+const CODE:    u8 = 0b1000; // Fetched as an instruction.
+
+// Suspicious things a program can do with its memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hazard {
+    UninitRead, // Read of a cell nothing has written.
+    ExecData,   // Fetch of a cell last written as data.
+    WriteCode,  // Write to a cell that has been executed.
+    JumpData,   // Jump to a cell only ever used as data.
+}
+
+// One report from the sanitizer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Finding {
+    pub hazard: Hazard,
+    pub cycle:  u64, // Clock ticks run, counting this one.
+    pub pc:     u8,  // Address of the instruction responsible.
+    pub addr:   u8,  // The ram cell involved.
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cycle {}, pc {:04b}: ", self.cycle, self.pc)?;
+        match self.hazard {
+            Hazard::UninitRead => write!(f, "read of uninitialized ram at {:04b}", self.addr),
+            Hazard::ExecData   => write!(f, "executing {:04b}, last written as data", self.addr),
+            Hazard::WriteCode  => write!(f, "write into code at {:04b}", self.addr),
+            Hazard::JumpData   => write!(f, "jump into data at {:04b}", self.addr),
+        }
+    }
+}
+
+// Shadow memory for one machine. Watches the RO and RI traffic of every
+// tick, and reports each kind of hazard once per address.
+#[derive(Default, Debug, Clone)]
+pub struct Sanitizer {
+    shadow:   [u8; 16],
+    reported: [u8; 16], // Hazards already reported, a bit per kind, by address.
+    cycles:   u64,
+    inst_pc:  u8,       // Address of the instruction being executed.
+}
+
+impl Sanitizer {
+    // `init` has bit n set for each address the program image filled in.
+    pub fn new(init: u16) -> Sanitizer {
+        let mut s = Sanitizer::default();
+        for (addr, cell) in s.shadow.iter_mut().enumerate() {
+            if init & (1 << addr) != 0 {
+                *cell = INIT;
+            }
+        }
+        s
+    }
+
+    // Checks one tick, given the state of the machine before and after it
+    // and the control word it ran.
+    pub fn check(&mut self, before: &EBC, after: &EBC, cw: ControlWord) -> Option<Finding> {
+        self.cycles += 1;
+        if before.sc == 0 {
+            self.inst_pc = before.pc & 0b1111;
+        }
+
+        let mar = before.mar & 0b1111;
+        let cell = self.shadow[mar as usize];
+        let fetch = !before.int_cycle && before.sc == 1;
+        let mut hazard = None;
+
+        if cw.has(signal::RO) && before.in_addr != Some(before.mar) {
+            if cell & INIT == 0 {
+                hazard = Some((Hazard::UninitRead, mar));
+            } else if fetch && cell & WRITTEN != 0 {
+                hazard = Some((Hazard::ExecData, mar));
+            }
+            self.shadow[mar as usize] |= if fetch { CODE } else { READ };
+        }
+        if cw.has(signal::RI) {
+            if cell & CODE != 0 {
+                hazard = Some((Hazard::WriteCode, mar));
+            }
+            self.shadow[mar as usize] = INIT | WRITTEN;
+        }
+        if cw.has(signal::J_) {
+            let target = after.pc & 0b1111;
+            let t = self.shadow[target as usize];
+            if t & (WRITTEN | READ) != 0 && t & CODE == 0 {
+                hazard = Some((Hazard::JumpData, target));
+            }
+        }
+
+        let (hazard, addr) = hazard?;
+        let bit = 1 << hazard as u8;
+        if self.reported[addr as usize] & bit != 0 {
+            return None;
+        }
+        self.reported[addr as usize] |= bit;
+        Some(Finding { hazard, cycle: self.cycles, pc: self.inst_pc, addr })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beebc;
+
+    // Runs a program for up to `max` ticks, or until it halts, giving
+    // everything the sanitizer found. `init` is as for Sanitizer::new.
+    fn findings(mut ram: [u8; 16], init: u16, in_addr: Option<u8>, max: usize) -> Vec<(Hazard, u8, u8)> {
+        let mut ebc = EBC { in_addr, ..EBC::default() };
+        let mut s = Sanitizer::new(init);
+        let mut found = Vec::new();
+        for _ in 0..max {
+            if ebc.hlt {
+                break;
+            }
+            let before = ebc.clone();
+            let cw = beebc::step(&mut ebc, &mut ram).unwrap();
+            found.extend(s.check(&before, &ebc, cw).map(|f| (f.hazard, f.pc, f.addr)));
+        }
+        found
+    }
+
+    #[test]
+    fn uninitialized_reads() {
+        // LDA 10, HLT - the image only fills in 0 and 1.
+        let mut ram = [0; 16];
+        ram[..2].copy_from_slice(&[0x1A, 0xF0]);
+        assert_eq!(findings(ram, 0b11, None, 100), [(Hazard::UninitRead, 0, 10)]);
+        assert_eq!(findings(ram, 0b11 | 1 << 10, None, 100), []);
+        // The input port is not ram.
+        assert_eq!(findings(ram, 0b11, Some(10), 100), []);
+    }
+
+    #[test]
+    fn each_hazard_is_reported_once_per_address() {
+        // LDA 10, JMP 0.
+        let mut ram = [0; 16];
+        ram[..2].copy_from_slice(&[0x1A, 0x60]);
+        assert_eq!(findings(ram, 0b11, None, 200), [(Hazard::UninitRead, 0, 10)]);
+    }
+
+    #[test]
+    fn writes_into_code() {
+        // LDI 5, STA 0, HLT.
+        let mut ram = [0; 16];
+        ram[..3].copy_from_slice(&[0x55, 0x40, 0xF0]);
+        assert_eq!(findings(ram, 0b111, None, 100), [(Hazard::WriteCode, 1, 0)]);
+    }
+
+    #[test]
+    fn executing_data() {
+        // LDA 14, STA 3, NOP, then whatever was stored - HLT.
+        let mut ram = [0; 16];
+        ram[..3].copy_from_slice(&[0x1E, 0x43, 0x00]);
+        ram[14] = 0xF0;
+        assert_eq!(findings(ram, 0b111 | 1 << 14, None, 100), [(Hazard::ExecData, 3, 3)]);
+    }
+
+    #[test]
+    fn jumps_into_data() {
+        // LDA 14, JMP 14, where 14 holds HLT.
+        let mut ram = [0; 16];
+        ram[..2].copy_from_slice(&[0x1E, 0x6E]);
+        ram[14] = 0xF0;
+        assert_eq!(findings(ram, 0b11 | 1 << 14, None, 100), [(Hazard::JumpData, 1, 14)]);
+    }
+}
@@ -1,21 +1,73 @@
+use core::ops::BitOr;
+
+// A control word - the set of signals asserted during one clock tick.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ControlWord(pub u32);
+
 // Control Word Signals
-pub const HLT: u16  = 0b1000000000000000;  // Halt clock
-pub const MI:  u16  = 0b0100000000000000;  // Memory address register in
-pub const RI:  u16  = 0b0010000000000000;  // RAM data in
-pub const RO:  u16  = 0b0001000000000000;  // RAM data out
-pub const IO:  u16  = 0b0000100000000000;  // Instruction register out
-pub const II:  u16  = 0b0000010000000000;  // Instruction register in
-pub const AI:  u16  = 0b0000001000000000;  // A register in
-pub const AO:  u16  = 0b0000000100000000;  // A register out
-pub const EO:  u16  = 0b0000000010000000;  // ALU out
-pub const SU:  u16  = 0b0000000001000000;  // ALU subtract
-pub const BI:  u16  = 0b0000000000100000;  // B register in
-pub const OI:  u16  = 0b0000000000010000;  // Output register in
-pub const CE:  u16  = 0b0000000000001000;  // Program counter enable
-pub const CO:  u16  = 0b0000000000000100;  // Program counter out
-pub const J_:  u16  = 0b0000000000000010;  // Jump (program counter in)
-pub const FI:  u16  = 0b0000000000000001;
+pub const HLT: ControlWord = ControlWord(0b000000001000000000000000);  // Halt clock
+pub const MI:  ControlWord = ControlWord(0b000000000100000000000000);  // Memory address register in
+pub const RI:  ControlWord = ControlWord(0b000000000010000000000000);  // RAM data in
+pub const RO:  ControlWord = ControlWord(0b000000000001000000000000);  // RAM data out
+pub const IO:  ControlWord = ControlWord(0b000000000000100000000000);  // Instruction register out
+pub const II:  ControlWord = ControlWord(0b000000000000010000000000);  // Instruction register in
+pub const AI:  ControlWord = ControlWord(0b000000000000001000000000);  // A register in
+pub const AO:  ControlWord = ControlWord(0b000000000000000100000000);  // A register out
+pub const EO:  ControlWord = ControlWord(0b000000000000000010000000);  // ALU out
+pub const SU:  ControlWord = ControlWord(0b000000000000000001000000);  // ALU subtract
+pub const BI:  ControlWord = ControlWord(0b000000000000000000100000);  // B register in
+pub const OI:  ControlWord = ControlWord(0b000000000000000000010000);  // Output register in
+pub const CE:  ControlWord = ControlWord(0b000000000000000000001000);  // Program counter enable
+pub const CO:  ControlWord = ControlWord(0b000000000000000000000100);  // Program counter out
+pub const J_:  ControlWord = ControlWord(0b000000000000000000000010);  // Jump (program counter in)
+pub const FI:  ControlWord = ControlWord(0b000000000000000000000001);  // Flags register in
+
+// Interrupt Signals
+pub const XI:  ControlWord = ControlWord(0b100000000000000000000000);  // Saved program counter (EPC) in
+pub const XO:  ControlWord = ControlWord(0b010000000000000000000000);  // Saved program counter (EPC) out
+pub const VO:  ControlWord = ControlWord(0b001000000000000000000000);  // Interrupt vector out
+pub const IA:  ControlWord = ControlWord(0b000100000000000000000000);  // Interrupt acknowledge
+pub const EI:  ControlWord = ControlWord(0b000010000000000000000000);  // Interrupt enable set
+pub const DI:  ControlWord = ControlWord(0b000001000000000000000000);  // Interrupt enable clear
+
+// No signals asserted.
+pub const NONE: ControlWord = ControlWord(0);
+
+// Every named signal, in the order they are displayed.
+pub const SIGNALS: [(&str, ControlWord); 22] = [
+    ("HLT", HLT), ("MI", MI), ("RI", RI), ("RO", RO), ("IO", IO), ("II", II),
+    ("AI", AI), ("AO", AO), ("EO", EO), ("SU", SU), ("BI", BI), ("OI", OI),
+    ("CE", CE), ("CO", CO), ("J_", J_), ("FI", FI),
+    ("XI", XI), ("XO", XO), ("VO", VO), ("IA", IA), ("EI", EI), ("DI", DI),
+];
+
+impl ControlWord {
+    // Combines signals. A const fn, so the microcode tables can use it.
+    pub const fn with(self, other: ControlWord) -> ControlWord {
+        ControlWord(self.0 | other.0)
+    }
+
+    // Whether every signal in `sig` is asserted.
+    pub fn has(self, sig: ControlWord) -> bool {
+        sig.0 != 0 && (self.0 & sig.0) == sig.0
+    }
+
+    // The named signals asserted in this word.
+    pub fn signals(self) -> impl Iterator<Item = (&'static str, ControlWord)> {
+        SIGNALS.iter().copied().filter(move |(_, s)| self.has(*s))
+    }
+}
+
+impl BitOr for ControlWord {
+    type Output = ControlWord;
+
+    fn bitor(self, rhs: ControlWord) -> ControlWord {
+        self.with(rhs)
+    }
+}
 
 // Flag Signals
 pub const CF: u8 = 0b01;
 pub const ZF: u8 = 0b10;
+pub const NF: u8 = 0b0100; // Negative - signed flags extension only.
+pub const VF: u8 = 0b1000; // Two's complement overflow - ^
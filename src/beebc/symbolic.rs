@@ -0,0 +1,623 @@
+// Symbolic execution. Chosen ram cells hold unknowns instead of numbers,
+// and the program is run over expressions, so what it outputs can be
+// worked out for every possible input at once.
+//
+// The control side of the machine - PC, step counter, MAR, IR - stays
+// concrete, and is driven by the real decoder. Only data is symbolic.
+// Where the run depends on an unknown (a conditional jump on a flag, or,
+// without the signed flags extension, whether the ALU overflowed) it
+// splits into one path per outcome, each carrying the conditions that
+// lead down it.
+use crate::beebc::{self, EBC};
+use crate::beebc::error::{Overflow, Undefined};
+use crate::beebc::signal::{self, ControlWord};
+use std::fmt;
+use std::rc::Rc;
+
+// An unknown value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Var {
+    Input(u8),  // The starting value of a ram cell.
+    Stale(u32), // What the ALU held after an overflow - not tracked.
+}
+
+// An 8 bit value. Arithmetic wraps, as on the adder chips.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Const(u8),
+    Var(Var),
+    Add(Rc<Expr>, Rc<Expr>),
+    Sub(Rc<Expr>, Rc<Expr>),
+}
+
+// A condition on values - what a flag holds, or a path was taken on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cond {
+    Const(bool),
+    Carry { sub: bool, a: Rc<Expr>, b: Rc<Expr> },    // Unsigned over or underflow.
+    Overflow { sub: bool, a: Rc<Expr>, b: Rc<Expr> }, // Two's complement overflow.
+    Zero(Rc<Expr>),
+    Neg(Rc<Expr>),
+    Not(Rc<Cond>),
+    And(Rc<Cond>, Rc<Cond>),
+    Or(Rc<Cond>, Rc<Cond>),
+}
+
+impl Expr {
+    fn eval(&self, env: &dyn Fn(Var) -> u8) -> u8 {
+        match self {
+            Expr::Const(v) => *v,
+            Expr::Var(v) => env(*v),
+            Expr::Add(a, b) => a.eval(env).wrapping_add(b.eval(env)),
+            Expr::Sub(a, b) => a.eval(env).wrapping_sub(b.eval(env)),
+        }
+    }
+
+    fn vars(&self, out: &mut Vec<Var>) {
+        match self {
+            Expr::Const(_) => {},
+            Expr::Var(v) => if !out.contains(v) { out.push(*v) },
+            Expr::Add(a, b) | Expr::Sub(a, b) => { a.vars(out); b.vars(out); },
+        }
+    }
+
+    // The value, if it does not depend on any unknowns.
+    fn konst(&self) -> Option<u8> {
+        let mut vars = Vec::new();
+        self.vars(&mut vars);
+        if vars.is_empty() { Some(self.eval(&|_| 0)) } else { None }
+    }
+
+    fn alu(sub: bool, a: &Rc<Expr>, b: &Rc<Expr>) -> Rc<Expr> {
+        let e = if sub { Expr::Sub(a.clone(), b.clone()) } else { Expr::Add(a.clone(), b.clone()) };
+        if let Some(v) = e.konst() {
+            return Rc::new(Expr::Const(v));
+        }
+        // Adding or taking away a constant folds into any constant already
+        // there, so a counter reads x + 3, not ((x + 1) + 1) + 1.
+        let k = match b.konst() {
+            Some(k) if sub => k.wrapping_neg(),
+            Some(k) => k,
+            None => return Rc::new(e),
+        };
+        let (base, k) = match &**a {
+            Expr::Add(x, c) if c.konst().is_some() => (x, k.wrapping_add(c.konst().unwrap())),
+            Expr::Sub(x, c) if c.konst().is_some() => (x, k.wrapping_sub(c.konst().unwrap())),
+            _ => (a, k),
+        };
+        match k {
+            0 => base.clone(),
+            1..=127 => Rc::new(Expr::Add(base.clone(), Rc::new(Expr::Const(k)))),
+            _ => Rc::new(Expr::Sub(base.clone(), Rc::new(Expr::Const(k.wrapping_neg())))),
+        }
+    }
+}
+
+impl Cond {
+    fn eval(&self, env: &dyn Fn(Var) -> u8) -> bool {
+        match self {
+            Cond::Const(c) => *c,
+            Cond::Carry { sub, a, b } => {
+                let (a, b) = (a.eval(env), b.eval(env));
+                if *sub { a.overflowing_sub(b).1 } else { a.overflowing_add(b).1 }
+            },
+            Cond::Overflow { sub, a, b } => {
+                let (a, b) = (a.eval(env) as i8, b.eval(env) as i8);
+                if *sub { a.overflowing_sub(b).1 } else { a.overflowing_add(b).1 }
+            },
+            Cond::Zero(e) => e.eval(env) == 0,
+            Cond::Neg(e) => e.eval(env) & 0x80 != 0,
+            Cond::Not(c) => !c.eval(env),
+            Cond::And(a, b) => a.eval(env) && b.eval(env),
+            Cond::Or(a, b) => a.eval(env) || b.eval(env),
+        }
+    }
+
+    fn vars(&self, out: &mut Vec<Var>) {
+        match self {
+            Cond::Const(_) => {},
+            Cond::Carry { a, b, .. } | Cond::Overflow { a, b, .. } => { a.vars(out); b.vars(out); },
+            Cond::Zero(e) | Cond::Neg(e) => e.vars(out),
+            Cond::Not(c) => c.vars(out),
+            Cond::And(a, b) | Cond::Or(a, b) => { a.vars(out); b.vars(out); },
+        }
+    }
+
+    // Folds conditions that do not depend on any unknowns.
+    fn new(c: Cond) -> Rc<Cond> {
+        let mut vars = Vec::new();
+        c.vars(&mut vars);
+        if vars.is_empty() {
+            Rc::new(Cond::Const(c.eval(&|_| 0)))
+        } else {
+            Rc::new(c)
+        }
+    }
+
+    fn not(c: &Rc<Cond>) -> Rc<Cond> {
+        match &**c {
+            Cond::Const(v) => Rc::new(Cond::Const(!v)),
+            Cond::Not(inner) => inner.clone(),
+            _ => Rc::new(Cond::Not(c.clone())),
+        }
+    }
+
+    fn and(a: &Rc<Cond>, b: &Rc<Cond>) -> Rc<Cond> {
+        match (&**a, &**b) {
+            (Cond::Const(false), _) | (_, Cond::Const(false)) => Rc::new(Cond::Const(false)),
+            (Cond::Const(true), _) => b.clone(),
+            (_, Cond::Const(true)) => a.clone(),
+            _ => Rc::new(Cond::And(a.clone(), b.clone())),
+        }
+    }
+
+    fn or(a: &Rc<Cond>, b: &Rc<Cond>) -> Rc<Cond> {
+        match (&**a, &**b) {
+            (Cond::Const(true), _) | (_, Cond::Const(true)) => Rc::new(Cond::Const(true)),
+            (Cond::Const(false), _) => b.clone(),
+            (_, Cond::Const(false)) => a.clone(),
+            _ => Rc::new(Cond::Or(a.clone(), b.clone())),
+        }
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Var::Input(addr) => write!(f, "[{:04b}]", addr),
+            Var::Stale(n) => write!(f, "stale{}", n),
+        }
+    }
+}
+
+// Compound parts of an expression are bracketed.
+struct Operand<'a>(&'a Expr);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Expr::Add(..) | Expr::Sub(..) => write!(f, "({})", self.0),
+            e => write!(f, "{}", e),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(v) => write!(f, "{}", v),
+            Expr::Var(v) => write!(f, "{}", v),
+            Expr::Add(a, b) => write!(f, "{} + {}", Operand(a), Operand(b)),
+            Expr::Sub(a, b) => write!(f, "{} - {}", Operand(a), Operand(b)),
+        }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cond::Const(v) => write!(f, "{}", v),
+            Cond::Carry { sub: false, a, b } => write!(f, "{} + {} > 255", Operand(a), Operand(b)),
+            Cond::Carry { sub: true, a, b } => write!(f, "{} < {}", Operand(a), Operand(b)),
+            Cond::Overflow { sub, a, b } => {
+                let op = if *sub { '-' } else { '+' };
+                write!(f, "signed {} {} {} overflows", Operand(a), op, Operand(b))
+            },
+            Cond::Zero(e) => write!(f, "{} == 0", e),
+            Cond::Neg(e) => write!(f, "{} >= 128", e),
+            Cond::Not(c) => match &**c {
+                Cond::Zero(e) => write!(f, "{} != 0", e),
+                Cond::Neg(e) => write!(f, "{} < 128", e),
+                Cond::Carry { sub: false, a, b } => write!(f, "{} + {} <= 255", Operand(a), Operand(b)),
+                Cond::Carry { sub: true, a, b } => write!(f, "{} >= {}", Operand(a), Operand(b)),
+                c => write!(f, "!({})", c),
+            },
+            Cond::And(a, b) => write!(f, "({} && {})", a, b),
+            Cond::Or(a, b) => write!(f, "({} || {})", a, b),
+        }
+    }
+}
+
+// How a path finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    PcOverflow,
+    UndefinedOpcode { addr: u8, op: u8 },
+    Unknown,     // An address, instruction or jump target depended on an input.
+    CycleLimit,
+}
+
+impl fmt::Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            End::Halted => write!(f, "halted"),
+            End::PcOverflow => write!(f, "program counter ran past the end of ram"),
+            End::UndefinedOpcode { addr, op } => write!(f, "undefined op code {:04b} at address {:04b}", op, addr),
+            End::Unknown => write!(f, "gave up - an address depends on the inputs"),
+            End::CycleLimit => write!(f, "gave up - cycle limit reached"),
+        }
+    }
+}
+
+// One way through the program.
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub conds:   Vec<Rc<Cond>>, // All hold on this path.
+    pub outputs: Vec<Rc<Expr>>,
+    pub end:     End,
+    pub cycles:  u64,
+}
+
+impl Path {
+    // How many input values take this path, if it can be counted - at most
+    // two inputs, and nothing stale.
+    pub fn count(&self) -> Option<(u64, u64)> {
+        let vars = cond_vars(&self.conds, None);
+        if vars.is_empty() || vars.len() > 2 || vars.iter().any(|v| matches!(v, Var::Stale(_))) {
+            return None;
+        }
+        let mut hits = 0;
+        let total = 1u64 << (8 * vars.len());
+        for n in 0..total {
+            let env = assignment(&vars, n);
+            if self.conds.iter().all(|c| c.eval(&env)) {
+                hits += 1;
+            }
+        }
+        Some((hits, total))
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let conds: Vec<String> = self.conds.iter().map(|c| c.to_string()).collect();
+        if conds.is_empty() {
+            writeln!(f, "  when: always")?;
+        } else {
+            writeln!(f, "  when: {}", conds.join(" && "))?;
+        }
+        if let Some((hits, total)) = self.count() {
+            writeln!(f, "        ({} of {} inputs)", hits, total)?;
+        }
+        for out in self.outputs.iter() {
+            writeln!(f, "  out:  {}", out)?;
+        }
+        writeln!(f, "  end:  {}, after {} cycles", self.end, self.cycles)
+    }
+}
+
+// Every path found, and whether the search stopped before finding them all.
+#[derive(Debug, Clone)]
+pub struct Exploration {
+    pub paths:     Vec<Path>,
+    pub truncated: bool,
+}
+
+// The machine part way down a path.
+#[derive(Debug, Clone)]
+struct State {
+    ebc:    EBC, // Control state. Its data registers are not used.
+    ram:    [Rc<Expr>; 16],
+    a:      Rc<Expr>,
+    b:      Rc<Expr>,
+    alu:    Rc<Expr>,
+    flags:  [Rc<Cond>; 4], // CF, ZF, NF, VF.
+    path:   Path,
+    stales: u32,
+}
+
+const FLAGS: [u8; 4] = [signal::CF, signal::ZF, signal::NF, signal::VF];
+
+// Runs a program with the ram cells in `inputs` (bit n for address n)
+// unknown, following every path up to `max_paths` of them, each for up to
+// `max_cycles` clock ticks.
+pub fn explore(ram: &[u8; 16], inputs: u16, nv_flags: bool, undef: Undefined,
+               overflow: Overflow, max_cycles: u64, max_paths: usize) -> Exploration {
+    let zero = Rc::new(Expr::Const(0));
+    let no = Rc::new(Cond::Const(false));
+    let cells: [Rc<Expr>; 16] = core::array::from_fn(|addr| {
+        if inputs & (1 << addr) != 0 {
+            Rc::new(Expr::Var(Var::Input(addr as u8)))
+        } else {
+            Rc::new(Expr::Const(ram[addr]))
+        }
+    });
+    let start = State {
+        ebc:    EBC { nv_flags, undef, overflow, ..EBC::default() },
+        ram:    cells,
+        a:      zero.clone(),
+        b:      zero.clone(),
+        alu:    zero,
+        flags:  [no.clone(), no.clone(), no.clone(), no],
+        path:   Path { conds: Vec::new(), outputs: Vec::new(), end: End::Halted, cycles: 0 },
+        stales: 0,
+    };
+
+    let mut todo = vec![start];
+    let mut done = Vec::new();
+    let mut truncated = false;
+    while let Some(mut s) = todo.pop() {
+        if done.len() >= max_paths {
+            truncated = true;
+            break;
+        }
+        let end = loop {
+            if s.path.cycles >= max_cycles {
+                break End::CycleLimit;
+            }
+            match s.tick() {
+                Ok(Some(other)) => {
+                    if done.len() + todo.len() + 1 < max_paths {
+                        todo.push(other);
+                    } else {
+                        truncated = true;
+                    }
+                },
+                Ok(None) => {},
+                Err(end) => break end,
+            }
+            if s.ebc.hlt {
+                break End::Halted;
+            }
+        };
+        s.path.end = end;
+        done.push(s.path);
+    }
+    Exploration { paths: done, truncated }
+}
+
+impl State {
+    // Whether `c` holds, if this path decides it. Conditions over at most
+    // two unknowns are settled by trying every value.
+    fn decide(&self, c: &Rc<Cond>, search: bool) -> Option<bool> {
+        if let Cond::Const(v) = **c {
+            return Some(v);
+        }
+        if self.path.conds.contains(c) {
+            return Some(true);
+        }
+        if self.path.conds.contains(&Cond::not(c)) {
+            return Some(false);
+        }
+        if !search {
+            return None;
+        }
+        let vars = cond_vars(&self.path.conds, Some(c));
+        if vars.len() > 2 {
+            return None;
+        }
+        let (mut yes, mut no) = (false, false);
+        for n in 0..1u64 << (8 * vars.len()) {
+            let env = assignment(&vars, n);
+            if self.path.conds.iter().all(|p| p.eval(&env)) {
+                if c.eval(&env) { yes = true; } else { no = true; }
+                if yes && no { return None; }
+            }
+        }
+        Some(yes)
+    }
+
+    // Settles `c` for this path. If it could go either way, this path
+    // takes it as true, and the path where it is false is returned.
+    fn split(&mut self, c: &Rc<Cond>, search: bool) -> (bool, Option<State>) {
+        match self.decide(c, search) {
+            Some(v) => (v, None),
+            None => {
+                let mut other = self.clone();
+                other.path.conds.push(Cond::not(c));
+                self.path.conds.push(c.clone());
+                (true, Some(other))
+            },
+        }
+    }
+
+    fn stale(&mut self) -> Rc<Expr> {
+        self.stales += 1;
+        Rc::new(Expr::Var(Var::Stale(self.stales)))
+    }
+
+    // One clock tick, as beebc::step does it. Returns the other side of
+    // any split, which is still to run this tick.
+    fn tick(&mut self) -> Result<Option<State>, End> {
+        let mut forked = None;
+        if self.ebc.sc == 0 && self.ebc.pc_wrap {
+            match self.ebc.overflow {
+                Overflow::Trap => return Err(End::PcOverflow),
+                Overflow::Wrap => self.ebc.pc_wrap = false,
+                Overflow::Halt => return Err(End::Halted),
+            }
+        }
+        if self.ebc.sc == 2 {
+            let op = self.ebc.ir >> 4;
+            let info = beebc::defined(&self.ebc, op);
+            if info.is_err() && self.ebc.undef == Undefined::Trap {
+                return Err(End::UndefinedOpcode { addr: self.ebc.mar, op });
+            }
+            // Conditional jumps - give the decoder the flag this path has.
+            if let Ok(info) = info {
+                if let Some(i) = FLAGS.iter().position(|f| *f == info.flag) {
+                    let flag = self.flags[i].clone();
+                    let (set, other) = self.split(&flag, true);
+                    forked = other;
+                    self.ebc.reg_flgs = if set { info.flag } else { 0 };
+                }
+            }
+        }
+        let cw = beebc::decode_instruction(&self.ebc);
+        self.update(cw, &mut forked)?;
+        self.path.cycles += 1;
+        Ok(forked)
+    }
+
+    // update_modules, over expressions.
+    fn update(&mut self, cw: ControlWord, forked: &mut Option<State>) -> Result<(), End> {
+        let e = &mut self.ebc;
+        let mut bus = Rc::new(Expr::Const(0));
+        if cw.has(signal::RO) { bus = self.ram[e.mar as usize].clone(); }
+        if cw.has(signal::IO) { bus = Rc::new(Expr::Const(e.ir & 0b1111)); }
+        if cw.has(signal::AO) { bus = self.a.clone(); }
+        if cw.has(signal::CO) { bus = Rc::new(Expr::Const(e.pc)); }
+        if cw.has(signal::XO) { bus = Rc::new(Expr::Const(e.epc)); }
+        if cw.has(signal::VO) { bus = Rc::new(Expr::Const(e.ivec & 0b1111)); }
+
+        let sub = cw.has(signal::SU);
+        let (a, b) = (self.a.clone(), self.b.clone());
+        let val = Expr::alu(sub, &a, &b);
+        if self.ebc.nv_flags {
+            self.alu = val.clone();
+            if cw.has(signal::FI) {
+                self.flags = [
+                    Cond::new(Cond::Carry { sub, a: a.clone(), b: b.clone() }),
+                    Cond::new(Cond::Zero(val.clone())),
+                    Cond::new(Cond::Neg(val.clone())),
+                    Cond::new(Cond::Overflow { sub, a, b }),
+                ];
+            }
+        } else {
+            // Without the extension the ALU holds its last value on an
+            // overflow. Only worth splitting over when it is looked at.
+            let carry = Cond::new(Cond::Carry { sub, a, b });
+            let seen = cw.has(signal::EO) || cw.has(signal::FI);
+            let over = if seen {
+                let (v, other) = self.split(&carry, true);
+                if other.is_some() {
+                    // The tick has not run on the other side yet.
+                    *forked = other;
+                }
+                Some(v)
+            } else {
+                self.decide(&carry, false)
+            };
+            match over {
+                Some(false) => {
+                    self.alu = val.clone();
+                    if cw.has(signal::FI) {
+                        // Sets ZF, and only ZF, on a zero result.
+                        let zero = Cond::new(Cond::Zero(val));
+                        self.flags[0] = Cond::and(&Cond::not(&zero), &self.flags[0]);
+                        self.flags[1] = Cond::or(&zero, &self.flags[1]);
+                    }
+                },
+                Some(true) => {
+                    if cw.has(signal::FI) {
+                        let (yes, no) = (Rc::new(Cond::Const(true)), Rc::new(Cond::Const(false)));
+                        self.flags = [yes, no.clone(), no.clone(), no];
+                    }
+                },
+                None => self.alu = self.stale(),
+            }
+        }
+        if cw.has(signal::EO) { bus = self.alu.clone(); }
+
+        let e = &mut self.ebc;
+        let known = |bus: &Rc<Expr>| bus.konst().ok_or(End::Unknown);
+        if cw.has(signal::MI) { e.mar = known(&bus)? & 0b1111; }
+        if cw.has(signal::RI) { self.ram[e.mar as usize] = bus.clone(); }
+        if cw.has(signal::II) { e.ir = known(&bus)?; }
+        if cw.has(signal::AI) { self.a = bus.clone(); }
+        if cw.has(signal::BI) { self.b = bus.clone(); }
+        if cw.has(signal::OI) { self.path.outputs.push(bus.clone()); }
+        if cw.has(signal::J_) {
+            e.pc = known(&bus)? & 0b1111;
+            e.pc_wrap = false;
+        }
+        if cw.has(signal::XI) { e.epc = known(&bus)?; }
+        if cw.has(signal::DI) { e.ie = false; }
+        if cw.has(signal::EI) { e.ie = true; }
+
+        e.sc = (e.sc + 1) & 0b111;
+        if cw.has(signal::CE) {
+            e.pc = (e.pc + 1) & 0b1111;
+            if e.pc == 0 {
+                e.pc_wrap = true;
+            }
+        }
+        if cw.has(signal::HLT) {
+            e.hlt = true;
+        }
+        Ok(())
+    }
+}
+
+// The unknowns in a set of conditions.
+fn cond_vars(conds: &[Rc<Cond>], extra: Option<&Rc<Cond>>) -> Vec<Var> {
+    let mut vars = Vec::new();
+    for c in conds.iter().chain(extra) {
+        c.vars(&mut vars);
+    }
+    vars.sort();
+    vars
+}
+
+// The n-th way of giving values to `vars`, a byte each.
+fn assignment(vars: &[Var], n: u64) -> impl Fn(Var) -> u8 + '_ {
+    move |v| {
+        let i = vars.iter().position(|x| *x == v).unwrap_or(0);
+        (n >> (8 * i)) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LDA 15, ADD 14, JZ 5, OUT, HLT, then LDI 7, OUT, HLT. Address 14
+    // holds 0, and 15 is the input - output it, or 7 if it is 0.
+    const BRANCH: [u8; 16] = [0x1F, 0x2E, 0x85, 0xE0, 0xF0, 0x57, 0xE0, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn known_inputs_take_one_path() {
+        let mut ram = BRANCH;
+        ram[15] = 3;
+        let found = explore(&ram, 0, false, Undefined::Trap, Overflow::Trap, 1000, 16);
+        assert_eq!(found.paths.len(), 1);
+        let path = &found.paths[0];
+        assert_eq!(path.end, End::Halted);
+        assert_eq!(path.outputs.iter().map(|o| o.to_string()).collect::<Vec<_>>(), ["3"]);
+    }
+
+    #[test]
+    fn splits_on_an_unknown_flag() {
+        let found = explore(&BRANCH, 1 << 15, false, Undefined::Trap, Overflow::Trap, 1000, 16);
+        assert!(!found.truncated);
+        assert_eq!(found.paths.len(), 2);
+        let mut counts: Vec<(u64, u64)> = found.paths.iter().map(|p| p.count().unwrap()).collect();
+        counts.sort();
+        assert_eq!(counts, [(1, 256), (255, 256)]);
+        assert!(found.paths.iter().all(|p| p.end == End::Halted && p.outputs.len() == 1));
+    }
+
+    #[test]
+    fn limits() {
+        // JMP 0 forever.
+        let mut ram = [0; 16];
+        ram[0] = 0x60;
+        let found = explore(&ram, 0, false, Undefined::Trap, Overflow::Trap, 100, 16);
+        assert_eq!(found.paths[0].end, End::CycleLimit);
+        // Falls off the end of ram.
+        let found = explore(&[0; 16], 0, false, Undefined::Trap, Overflow::Trap, 1000, 16);
+        assert_eq!(found.paths[0].end, End::PcOverflow);
+        let found = explore(&[0; 16], 0, false, Undefined::Trap, Overflow::Halt, 1000, 16);
+        assert_eq!(found.paths[0].end, End::Halted);
+        let found = explore(&[0; 16], 0, false, Undefined::Trap, Overflow::Wrap, 1000, 16);
+        assert_eq!(found.paths[0].end, End::CycleLimit);
+    }
+
+    #[test]
+    fn unknown_jump_target_gives_up() {
+        // Storing the input over the next instruction makes it unknown.
+        let mut ram = [0; 16];
+        ram[0] = 0x1F; // LDA 15
+        ram[1] = 0x42; // STA 2
+        let found = explore(&ram, 1 << 15, false, Undefined::Trap, Overflow::Trap, 1000, 16);
+        assert_eq!(found.paths[0].end, End::Unknown);
+    }
+
+    #[test]
+    fn too_many_paths() {
+        let found = explore(&BRANCH, 1 << 15, false, Undefined::Trap, Overflow::Trap, 1000, 1);
+        assert!(found.truncated);
+        assert_eq!(found.paths.len(), 1);
+    }
+}
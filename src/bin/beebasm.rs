@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 extern crate rustybeebc;
 use rustybeebc::beebc;
+use rustybeebc::beebc::asm::{Level, Options};
+use std::process;
 
 use clap::{Arg, App};
 
@@ -17,9 +19,72 @@ fn main() {
                 .help("output binary machine code file.")
                 .required(true)
                 .index(2))
+            .arg(Arg::with_name("include")
+                .short("I")
+                .long("include")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("DIR")
+                .help("Looks for .include files in DIR, after the including file's own directory."))
+            .arg(Arg::with_name("define")
+                .short("D")
+                .long("define")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME=VALUE")
+                .help("Defines a constant, as .equ would. VALUE is 1 if left out."))
+            .arg(Arg::with_name("listing")
+                .long("listing")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes a listing of each line's address, bytes and cycles, and the symbols, to FILE."))
+            .arg(Arg::with_name("ram_size")
+                .long("ram-size")
+                .takes_value(true)
+                .value_name("BYTES")
+                .help("Only lets the program use this much ram, 1 to 16 bytes from address 0. Defaults to all 16."))
+            .arg(Arg::with_name("used")
+                .long("used")
+                .help("Also writes the addresses the program fills in to OUTPUT.used, for beebc's --sanitize and --random-power-on."))
+            .arg(Arg::with_name("legacy_labels")
+                .long("legacy-labels")
+                .help("Takes a word alone on a line as a label, as older sources wrote them, with a warning."))
             .get_matches();
 
     let file_name = String::from(ebc_cli.value_of("INPUT").unwrap());
     let out_name  = String::from(ebc_cli.value_of("OUTPUT").unwrap());
-    beebc::asm::assemble(&file_name, &out_name);
+    let mut options = Options {
+        include_dirs:  ebc_cli.values_of("include").map_or(Vec::new(), |v| v.map(String::from).collect()),
+        listing:       ebc_cli.value_of("listing").map(String::from),
+        legacy_labels: ebc_cli.is_present("legacy_labels"),
+        used:          ebc_cli.is_present("used"),
+        ..Options::default()
+    };
+    if let Some(v) = ebc_cli.value_of("ram_size") {
+        match v.parse::<usize>() {
+            Ok(n) if (1..=16).contains(&n) => options.ram_size = Some(n),
+            _ => {
+                eprintln!("error: --ram-size {}: must be 1 to 16 bytes", v);
+                process::exit(1);
+            }
+        }
+    }
+    for spec in ebc_cli.values_of("define").into_iter().flatten() {
+        if let Err(e) = options.define(spec) {
+            eprintln!("error: -D {}: {}", spec, e);
+            process::exit(1);
+        }
+    }
+    let diags = beebc::asm::assemble(&file_name, &out_name, &options);
+    for d in diags.iter() {
+        eprint!("{}", d);
+    }
+    let errors = diags.iter().filter(|d| d.level == Level::Error).count();
+    if errors > 0 {
+        eprintln!("error: could not assemble {:?}, {} error{}", file_name, errors, if errors == 1 { "" } else { "s" });
+        process::exit(1);
+    }
+    println!("assembled {:?} to {:?}", file_name, out_name);
 }
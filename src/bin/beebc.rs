@@ -1,10 +1,22 @@
 #![allow(dead_code)]
 extern crate rustybeebc;
 use rustybeebc::beebc;
+use rustybeebc::beebc::periph::Timer;
+use rustybeebc::beebc::link::{Machine, MachineError, Link, System};
+use rustybeebc::beebc::error::{Overflow, Undefined};
+use rustybeebc::beebc::profile::Profile;
+use rustybeebc::beebc::loops::LoopDetector;
+use rustybeebc::beebc::power;
+use rustybeebc::beebc::sanitize::Sanitizer;
+use rustybeebc::beebc::provenance::{self, Provenance};
+use rustybeebc::beebc::symbolic;
+use rustybeebc::beebc::protect::Protection;
+use rustybeebc::beebc::clock::{Clock, Mode};
 use clap::{Arg, App};
-use std::time;
-use std::fs::File;
-use std::io::{self, stdout, Read};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::fs;
+use std::io::{self, stdout};
+use std::process::ExitCode;
 use termion::{
     event::{Key},
     input::{TermRead},
@@ -12,81 +24,641 @@ use termion::{
     screen::AlternateScreen,
 };
 use tui::{
-    backend::{TermionBackend},
+    backend::{Backend, TermionBackend},
     Terminal
 };
 
-fn main() -> io::Result<()> {
+// Process exit codes, so scripts can tell how a run ended.
+const EXIT_HALTED: u8 = 0;
+const EXIT_ERROR:  u8 = 1;
+const EXIT_LIMIT:  u8 = 2;
+const EXIT_LOOP:   u8 = 3;
+
+// Default limits for symbolic mode - cycles down each path, and paths.
+const SYMBOLIC_CYCLES: u64 = 10_000;
+const SYMBOLIC_PATHS: usize = 256;
+
+fn main() -> io::Result<ExitCode> {
     let ebc_cli = App::new("beebc emulator.")
             .version("0.1.0")
             .author("wpower12 <willpowe@gmail.com>")
             .about("Runs beebc machine code.")
             .arg(Arg::with_name("INPUT")
-                .help("input machine code binary tile.")
+                .help("input machine code binary tile. Give several to run linked machines.")
                 .required(true)
+                .multiple(true)
                 .index(1))
             .arg(Arg::with_name("gui")
                 .short("g")
                 .long("gui")
                 .help("Starts emulator in graphical mode."))
+            .arg(Arg::with_name("timer")
+                .long("timer")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("[M:]TICKS")
+                .help("Attaches a timer raising an interrupt every TICKS clock ticks. With several machines, give the machine M."))
+            .arg(Arg::with_name("ivec")
+                .long("irq-vector")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("[M:]ADDR")
+                .help("Address of the interrupt handler. Defaults to 0. With several machines, give the machine M."))
+            .arg(Arg::with_name("in_addr")
+                .long("in-addr")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("[M:]ADDR")
+                .help("Maps the input port over this RAM address. With several machines, give the machine M."))
+            .arg(Arg::with_name("link")
+                .long("link")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("SRC:DST[:irq]")
+                .help("Wires machine SRC's output register to machine DST's input port. With :irq, DST is interrupted whenever SRC latches its output."))
+            .arg(Arg::with_name("link_irq")
+                .long("link-irq")
+                .help("Every link raises an interrupt on DST whenever SRC latches its output."))
+            .arg(Arg::with_name("div")
+                .long("div")
+                .takes_value(true)
+                .value_name("D0,D1,..")
+                .help("Clock dividers, one per machine. Machine i ticks every Di system ticks."))
+            .arg(Arg::with_name("clock")
+                .long("clock")
+                .takes_value(true)
+                .value_name("MODE")
+                .help("manual, free, or a frequency in Hz (e.g. 10, 2.5k). Defaults to 2 in gui mode, free otherwise."))
+            .arg(Arg::with_name("clock_report")
+                .long("clock-report")
+                .help("Prints the achieved clock frequency on exit."))
+            .arg(Arg::with_name("undefined")
+                .long("undefined")
+                .takes_value(true)
+                .possible_values(&["trap", "nop", "halt"])
+                .default_value("trap")
+                .help("What to do with undefined op codes."))
+            .arg(Arg::with_name("pc_overflow")
+                .long("pc-overflow")
+                .takes_value(true)
+                .possible_values(&["trap", "wrap", "halt"])
+                .default_value("trap")
+                .help("What to do when the PC runs past the end of ram."))
+            .arg(Arg::with_name("nv_flags")
+                .long("nv-flags")
+                .help("Turns on the signed flags extension - N and V flags, JN and JV."))
+            .arg(Arg::with_name("signed")
+                .long("signed")
+                .help("Shows output as signed, two's complement numbers."))
+            .arg(Arg::with_name("profile")
+                .long("profile")
+                .help("Counts cycles, instructions and memory traffic, and prints a report on exit. Press r to view it in gui mode."))
+            .arg(Arg::with_name("max_cycles")
+                .long("max-cycles")
+                .takes_value(true)
+                .value_name("N")
+                .help("Stops after N clock cycles (batch mode)."))
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("SECS")
+                .help("Stops after SECS seconds (batch mode)."))
+            .arg(Arg::with_name("detect_loops")
+                .long("detect-loops")
+                .help("Stops when the machine state repeats, which proves it will never halt (batch mode)."))
+            .arg(Arg::with_name("random_power_on")
+                .long("random-power-on")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .value_name("SEED")
+                .help("Starts with garbage in the registers and unused RAM, like the real chips. The seed is printed, so a run can be repeated."))
+            .arg(Arg::with_name("sanitize")
+                .long("sanitize")
+                .help("Reports reads of uninitialized RAM, executing data, writes into code and jumps into data."))
+            .arg(Arg::with_name("explain")
+                .long("explain")
+                .help("Tracks where every value came from, and explains each output as the loads, adds and stores that made it (batch mode)."))
+            .arg(Arg::with_name("symbolic")
+                .long("symbolic")
+                .takes_value(true)
+                .value_name("A0,A1,..")
+                .help("Treats these RAM addresses as unknown inputs, and prints what the program outputs on every path through it."))
+            .arg(Arg::with_name("protect")
+                .long("protect")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("KIND:RANGE")
+                .help("Protects RAM - ro (read-only), xo (execute-only) or nx (no-execute), over addresses like 0-7,12. Also read from INPUT.protect."))
+            .arg(Arg::with_name("protect_warn")
+                .long("protect-warn")
+                .help("Warns about protection breaches, instead of stopping."))
             .get_matches();
 
-    let file_name = String::from(ebc_cli.value_of("INPUT").unwrap());
-    println!("running: {:?}", file_name);
+    // Settings for one machine in particular.
+    let machines = ebc_cli.values_of("INPUT").unwrap().len();
+    let per = |name: &str, option: &str, max: u32| per_machine(ebc_cli.values_of(name), option, max, machines);
+    let ivecs = per("ivec", "irq-vector", u8::MAX.into());
+    let in_addrs = per("in_addr", "in-addr", u8::MAX.into());
+    let timers = per("timer", "timer", u32::MAX);
+    let (ivecs, in_addrs, timers) = match (ivecs, in_addrs, timers) {
+        (Ok(ivecs), Ok(in_addrs), Ok(timers)) => (ivecs, in_addrs, timers),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("error: {}", e);
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
+    };
+    let divs = match dividers(ebc_cli.value_of("div").unwrap_or(""), machines) {
+        Ok(divs) => divs,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
+    };
+    let max_cycles = match ebc_cli.value_of("max_cycles").map(|v| v.trim().parse::<u64>()).transpose() {
+        Ok(max) => max,
+        Err(_) => {
+            eprintln!("error: max-cycles must be a number of cycles, not {:?}.", ebc_cli.value_of("max_cycles").unwrap());
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
+    };
+    let timeout = match ebc_cli.value_of("timeout").map(|v| v.trim().parse::<f64>().map(Duration::try_from_secs_f64)) {
+        None => None,
+        Some(Ok(Ok(t))) => Some(t),
+        Some(_) => {
+            eprintln!("error: timeout must be a number of seconds, 0 or more, not {:?}.", ebc_cli.value_of("timeout").unwrap());
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
+    };
+
+    let undef = match ebc_cli.value_of("undefined") {
+        Some("nop") => Undefined::Nop,
+        Some("halt") => Undefined::Halt,
+        _ => Undefined::Trap
+    };
+    let overflow = match ebc_cli.value_of("pc_overflow") {
+        Some("wrap") => Overflow::Wrap,
+        Some("halt") => Overflow::Halt,
+        _ => Overflow::Trap
+    };
+
+    let power_seed = if ebc_cli.is_present("random_power_on") {
+        let seed = match ebc_cli.value_of("random_power_on") {
+            Some(v) => match v.trim().parse::<u64>() {
+                Ok(seed) => seed,
+                Err(_) => {
+                    eprintln!("error: random-power-on seed must be a number, not {:?}.", v);
+                    return Ok(ExitCode::from(EXIT_ERROR));
+                }
+            },
+            None => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+        };
+        println!("power-on seed: {}", seed);
+        Some(seed)
+    } else {
+        None
+    };
+
+    let mut sys = System::new();
+    for (i, file_name) in ebc_cli.values_of("INPUT").unwrap().enumerate() {
+        println!("running: {:?}", file_name);
+        let mut ram: [u8; 16] = [0; 16];    
+        let image = fs::read(file_name)?;
+        if let Err(e) = beebc::load(&mut ram, &image) {
+            eprintln!("error: {}: {}", file_name, e);
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
 
+        let mut m = Machine::new(ram);
+        m.ebc.undef = undef;
+        m.ebc.overflow = overflow;
+        m.ebc.nv_flags = ebc_cli.is_present("nv_flags");
+        if ebc_cli.is_present("profile") {
+            m.profile = Some(Profile::new());
+        }
+        m.ebc.ivec = ivecs[i].unwrap_or(0) as u8;
+        m.ebc.in_addr = in_addrs[i].map(|a| a as u8);
+        let cells = match image_cells(file_name, &image) {
+            Ok(cells) => cells,
+            Err(e) => {
+                eprintln!("error: {}.used: {}", file_name, e);
+                return Ok(ExitCode::from(EXIT_ERROR));
+            }
+        };
+        if let Some(seed) = power_seed {
+            power::power_on(&mut m.ebc, &mut m.ram, cells, seed.wrapping_add(i as u64));
+        }
+        if ebc_cli.is_present("sanitize") {
+            m.sanitizer = Some(Sanitizer::new(cells));
+        }
+        // Rules from the command line, plus any kept next to the program.
+        let mut protect = Protection { warn: ebc_cli.is_present("protect_warn"), ..Protection::default() };
+        let mut specs: Vec<String> = ebc_cli.values_of("protect").map_or(Vec::new(), |v| v.map(String::from).collect());
+        if let Ok(meta) = fs::read_to_string(format!("{}.protect", file_name)) {
+            specs.extend(meta.split_whitespace().map(String::from));
+        }
+        for spec in specs.iter() {
+            if let Err(e) = add_protection(&mut protect, spec) {
+                eprintln!("error: protect: {}", e);
+                return Ok(ExitCode::from(EXIT_ERROR));
+            }
+        }
+        m.ebc.protect = protect;
+        if ebc_cli.is_present("explain") {
+            m.provenance = Some(Provenance::new(&m.ram));
+        }
+        m.div = divs[i];
+        if let Some(period) = timers[i].filter(|p| *p > 0) {
+            m.periphs.push(Box::new(Timer::new(period)));
+        }
+        sys.machines.push(m);
+    }
+    if let Some(links) = ebc_cli.values_of("link") {
+        for l in links {
+            match parse_link(l, sys.machines.len()) {
+                Ok(mut link) => {
+                    link.irq |= ebc_cli.is_present("link_irq");
+                    sys.links.push(link);
+                },
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    return Ok(ExitCode::from(EXIT_ERROR));
+                }
+            }
+        }
+    }
+    let linked = sys.machines.len() > 1;
+    if let Some(v) = ebc_cli.value_of("symbolic") {
+        if linked {
+            eprintln!("error: symbolic mode runs a single machine.");
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
+        let mut inputs = 0u16;
+        for a in v.split(',') {
+            match a.trim().parse::<u8>() {
+                Ok(a) if a < 16 => inputs |= 1 << a,
+                _ => {
+                    eprintln!("error: symbolic must be a list of addresses, 0 to 15, not {:?}.", v);
+                    return Ok(ExitCode::from(EXIT_ERROR));
+                }
+            }
+        }
+        let m = &sys.machines[0];
+        let found = symbolic::explore(&m.ram, inputs, m.ebc.nv_flags, m.ebc.undef, m.ebc.overflow, max_cycles.unwrap_or(SYMBOLIC_CYCLES), SYMBOLIC_PATHS);
+        for (i, path) in found.paths.iter().enumerate() {
+            println!("path {} of {}", i + 1, found.paths.len());
+            print!("{}", path);
+        }
+        if found.truncated {
+            eprintln!("limit: stopped after {} paths, there are more.", found.paths.len());
+            return Ok(ExitCode::from(EXIT_LIMIT));
+        }
+        return Ok(ExitCode::from(EXIT_HALTED));
+    }
+    let signed = ebc_cli.is_present("signed");
 
-    let mut ebc = beebc::EBC::default();
-    let mut ram: [u8; 16] = [0; 16];    
-    let mut f = File::open(file_name)?; 
-    let _ = f.read(&mut ram[..])?;
+    let gui = ebc_cli.occurrences_of("gui") > 0;
+    let mode = match ebc_cli.value_of("clock") {
+        Some(v) => match v.parse::<Mode>() {
+            Ok(mode) => mode,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return Ok(ExitCode::from(EXIT_ERROR));
+            }
+        },
+        None if gui => Mode::RealTime(2.0),
+        None => Mode::Unthrottled
+    };
+    let mut clock = Clock::new(mode);
+    let mut failure: Option<MachineError> = None;
+    let mut limit: Option<String> = None;
+    let mut looping: Option<u64> = None;
     
-    if ebc_cli.occurrences_of("gui") > 0 {
+    if gui {
         // Graphical Mode
         let stdout = stdout().into_raw_mode()?;
         let stdout = AlternateScreen::from(stdout);
         let backend = TermionBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
         let mut stdin = termion::async_stdin().keys();
-        
-        let mut running = true;
-
-        let mut now = time::Instant::now();
-        let tick_period = time::Duration::from_millis(500);
+        let mut show_profile = false;
+        draw(&mut terminal, &sys, signed, show_profile);
 
         loop {
             //handle inputs
             if let Some(Ok(key)) = stdin.next() {
                 match key {
-                    Key::Char('q') => return Ok(()),
-                    Key::Char('p') => running = !running,
+                    Key::Char('q') => break,
+                    Key::Char('p') => {
+                        if clock.paused() { clock.resume(); } else { clock.pause(); }
+                    },
+                    Key::Char('r') => {
+                        show_profile = !show_profile;
+                        draw(&mut terminal, &sys, signed, show_profile);
+                    },
+                    Key::Char('x') => {
+                        for m in sys.machines.iter_mut() {
+                            power::reset(&mut m.ebc);
+                        }
+                        failure = None;
+                        draw(&mut terminal, &sys, signed, show_profile);
+                    },
                     Key::Char('s') => {
-                        let cw = beebc::decode_instruction(&ebc);
-                        beebc::update_modules(&mut ebc, cw, ram);
-                        beebc::gui::render(&mut terminal, &ebc, ram, cw);
+                        if clock.mode == Mode::Manual {
+                            clock.pulse();
+                        } else {
+                            if let Err(e) = sys.tick() { failure = Some(e); }
+                            draw(&mut terminal, &sys, signed, show_profile);
+                        }
                     },
                     _ => {}
                 }
             }
 
-            // Handle Timers - To control cpu speed.
-            if now.elapsed() > tick_period && running {
-                let cw = beebc::decode_instruction(&ebc);
-                beebc::update_modules(&mut ebc, cw, ram);
-                beebc::gui::render(&mut terminal, &ebc, ram, cw);
-                now = time::Instant::now();
+            // Run whatever ticks the clock says are due, then redraw once.
+            // Errors pause the clock, so the machine can be inspected.
+            let due = clock.due();
+            if due > 0 {
+                for _ in 0..due {
+                    if let Err(e) = sys.tick() {
+                        failure = Some(e);
+                        clock.pause();
+                        break;
+                    }
+                    clock.tick();
+                }
+                draw(&mut terminal, &sys, signed, show_profile);
+            } else {
+                clock.wait(Duration::from_millis(10));
             }
         }
+        drop(terminal);
 
     } else {
         // "Batch" mode
-        while !ebc.hlt {
-            let new_cw = beebc::decode_instruction(&ebc);
-            beebc::update_modules(&mut ebc, new_cw, ram);
-            if (new_cw & beebc::signal::OI) > 0 { 
-                println!("{:?}", ebc.reg_out);
+        let mut detector = None;
+        if ebc_cli.is_present("detect_loops") {
+            if sys.snapshot().is_some() {
+                detector = Some(LoopDetector::new());
+            } else {
+                eprintln!("warning: a peripheral cannot report its state, loop detection is off.");
             }
         }
+        let started = Instant::now();
+
+        let stdin = io::stdin();
+        'run: while !sys.halted() {
+            if let Some(t) = timeout {
+                if started.elapsed() >= t {
+                    limit = Some(format!("timed out after {:.3}s", t.as_secs_f64()));
+                    break;
+                }
+            }
+
+            if clock.mode == Mode::Manual { // One tick per line of input.
+                let mut line = String::new();
+                if stdin.read_line(&mut line)? == 0 {
+                    limit = Some(format!("input ended after {} cycles, before the machine halted", sys.ticks));
+                    break;
+                }
+                clock.pulse();
+            }
+
+            let due = clock.due();
+            if due == 0 {
+                clock.wait(Duration::from_millis(10));
+                continue;
+            }
+            for _ in 0..due {
+                if sys.halted() { break; }
+                if max_cycles.is_some_and(|max| sys.ticks >= max) {
+                    limit = Some(format!("stopped after {} cycles", sys.ticks));
+                    break 'run;
+                }
+                let outputs = match sys.tick() {
+                    Ok(outputs) => outputs,
+                    Err(e) => {
+                        failure = Some(e);
+                        break 'run;
+                    }
+                };
+                report_warnings(&mut sys);
+                for i in outputs {
+                    let out = sys.machines[i].ebc.reg_out;
+                    let text = if signed { format!("{}", out as i8) } else { format!("{}", out) };
+                    if linked {
+                        println!("[{}] {}", i, text);
+                    } else {
+                        println!("{}", text);
+                    }
+                    if let Some(p) = &sys.machines[i].provenance {
+                        print!("{}", provenance::explain(p.out()));
+                    }
+                }
+                clock.tick();
+
+                if let Some(d) = detector.as_mut() {
+                    if let Some(n) = d.check(sys.snapshot().unwrap()) {
+                        looping = Some(n);
+                        break 'run;
+                    }
+                }
+            }
+        }
+    }
+
+    report_warnings(&mut sys);
+    if ebc_cli.is_present("profile") {
+        print!("{}", profile_report(&sys));
+    }
+    if ebc_cli.is_present("clock_report") {
+        println!("{}", clock.report());
+    }
+    if let Some(e) = failure {
+        eprintln!("error: {}", e);
+        return Ok(ExitCode::from(EXIT_ERROR));
+    }
+    if let Some(msg) = limit {
+        eprintln!("limit: {}", msg);
+        return Ok(ExitCode::from(EXIT_LIMIT));
+    }
+    if let Some(n) = looping {
+        eprintln!("loop: machine state repeats every {} cycles, it will never halt (at cycle {}).", n, sys.ticks);
+        return Ok(ExitCode::from(EXIT_LOOP));
+    }
+    Ok(ExitCode::from(EXIT_HALTED))
+}
+
+// The values of an option given per machine, as M:VALUE, by machine. A
+// lone machine can be given just VALUE.
+fn per_machine(values: Option<clap::Values>, option: &str, max: u32, machines: usize) -> Result<Vec<Option<u32>>, String> {
+    let mut found = vec![None; machines];
+    for v in values.into_iter().flatten() {
+        let (i, value) = match v.split_once(':') {
+            Some((i, value)) => match i.trim().parse::<usize>() {
+                Ok(i) if i < machines => (i, value),
+                _ => return Err(format!("{} {:?} does not name a loaded machine", option, v)),
+            },
+            None if machines == 1 => (0, v),
+            None => return Err(format!("{} {:?} must name its machine, as M:VALUE, when several are loaded", option, v)),
+        };
+        match value.trim().parse::<u32>() {
+            Ok(value) if value <= max => found[i] = Some(value),
+            _ => return Err(format!("{} {:?} must be a number, 0 to {}", option, v, max)),
+        }
+    }
+    Ok(found)
+}
+
+// Clock dividers, D0,D1,.. one per machine. Machines left out tick on
+// every system tick.
+fn dividers(list: &str, machines: usize) -> Result<Vec<u32>, String> {
+    let mut divs = vec![1; machines];
+    for (i, d) in list.split(',').filter(|_| !list.trim().is_empty()).enumerate() {
+        if i >= machines {
+            return Err(format!("div {:?} has more dividers than the {} machines loaded", list, machines));
+        }
+        match d.trim().parse::<u32>() {
+            Ok(d) if d > 0 => divs[i] = d,
+            _ => return Err(format!("div {:?} must be a list of numbers, 1 or more", list)),
+        }
     }
+    Ok(divs)
+}
 
+// A link, SRC:DST or SRC:DST:irq, between two of the loaded machines.
+fn parse_link(spec: &str, machines: usize) -> Result<Link, String> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    let irq = match parts.get(2) {
+        None => false,
+        Some(&"irq") if parts.len() == 3 => true,
+        _ => return Err(format!("link {:?} should look like SRC:DST or SRC:DST:irq", spec)),
+    };
+    let ends: Vec<usize> = parts.iter().take(2).filter_map(|e| e.trim().parse::<usize>().ok()).collect();
+    if parts.len() < 2 || ends.len() != 2 || ends.iter().any(|e| *e >= machines) {
+        return Err(format!("link {:?} does not name two loaded machines", spec));
+    }
+    Ok(Link { from: ends[0], to: ends[1], irq })
+}
+
+// The cells a program image fills in, as listed by the assembler in a file
+// named after the program with .used added. Without one, RAM past the
+// image's last non-zero byte is taken to be unused.
+fn image_cells(file_name: &str, image: &[u8]) -> Result<u16, String> {
+    match fs::read_to_string(format!("{}.used", file_name)) {
+        Ok(list) => address_mask(list.trim()),
+        Err(_) => {
+            let used = image.iter().rposition(|b| *b != 0).map_or(0, |last| last + 1);
+            Ok(((1u32 << used) - 1) as u16)
+        }
+    }
+}
+
+// Prints, and forgets, what the sanitizer has found, and the ram access
+// rules broken, since the last call.
+fn report_warnings(sys: &mut System) {
+    let linked = sys.machines.len() > 1;
+    for (i, m) in sys.machines.iter_mut().enumerate() {
+        let which = if linked { format!("machine {}: ", i) } else { String::new() };
+        for f in m.findings.drain(..) {
+            eprintln!("sanitize: {}{}", which, f);
+        }
+        for e in m.breaches.drain(..) {
+            eprintln!("warning: {}{}", which, e);
+        }
+    }
+}
+
+// Adds one access rule, KIND:RANGE[,RANGE..], to `p`. KIND is ro, xo or nx,
+// and a RANGE is an address or two joined by '-'.
+fn add_protection(p: &mut Protection, spec: &str) -> Result<(), String> {
+    let (kind, ranges) = spec.split_once(':').ok_or(format!("{:?} should look like KIND:RANGE", spec))?;
+    let mask = address_mask(ranges)?;
+    match kind.trim() {
+        "ro" => p.read_only |= mask,
+        "xo" => p.exec_only |= mask,
+        "nx" => p.no_exec |= mask,
+        k => return Err(format!("unknown protection {:?}, expected ro, xo or nx", k)),
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+// A bit for each address in a list like 0-7,12 - addresses, or two joined
+// by '-' for those between. An empty list has none.
+fn address_mask(ranges: &str) -> Result<u16, String> {
+    let mut mask: u16 = 0;
+    for r in ranges.split(',').filter(|_| !ranges.trim().is_empty()) {
+        let (lo, hi) = r.split_once('-').unwrap_or((r, r));
+        let lo = lo.trim().parse::<u8>().map_err(|_| format!("bad address in {:?}", ranges))?;
+        let hi = hi.trim().parse::<u8>().map_err(|_| format!("bad address in {:?}", ranges))?;
+        if lo > hi || hi > 15 {
+            return Err(format!("bad range {:?}, addresses run from 0 to 15", r));
+        }
+        mask |= (((1u32 << (hi + 1)) - 1) as u16) & !(((1u32 << lo) - 1) as u16);
+    }
+    Ok(mask)
+}
+
+fn profile_report(sys: &System) -> String {
+    let mut report = String::new();
+    for (i, m) in sys.machines.iter().enumerate() {
+        if let Some(p) = &m.profile {
+            report += &format!("profile, machine {}\n{}", i, p);
+        }
+    }
+    report
+}
+
+// A lone machine keeps the original layout, linked ones are drawn side by side.
+fn draw<B: Backend>(terminal: &mut Terminal<B>, sys: &System, signed: bool, show_profile: bool) {
+    if show_profile {
+        beebc::gui::render_text(terminal, "PROFILE - r to return", &profile_report(sys));
+    } else if sys.machines.len() == 1 {
+        let m = &sys.machines[0];
+        beebc::gui::render(terminal, &m.ebc, m.ram, m.last_cw, signed);
+    } else {
+        beebc::gui::render_system(terminal, sys, signed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links() {
+        let l = parse_link("0:1", 2).unwrap();
+        assert_eq!((l.from, l.to, l.irq), (0, 1, false));
+        let l = parse_link("1:0:irq", 2).unwrap();
+        assert_eq!((l.from, l.to, l.irq), (1, 0, true));
+        for bad in ["0", "0:2", "0:x", "0:1:nmi", "0:1:irq:irq"] {
+            assert!(parse_link(bad, 2).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn divs() {
+        assert_eq!(dividers("", 2), Ok(vec![1, 1]));
+        assert_eq!(dividers("3", 2), Ok(vec![3, 1]));
+        assert_eq!(dividers("1, 4", 2), Ok(vec![1, 4]));
+        for bad in ["0", "a", "1,,2", "1,2,3"] {
+            assert!(dividers(bad, 2).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn address_masks() {
+        assert_eq!(address_mask(""), Ok(0));
+        assert_eq!(address_mask("3"), Ok(1 << 3));
+        assert_eq!(address_mask("0-3, 15"), Ok(0b1000_0000_0000_1111));
+        assert_eq!(address_mask("0-15"), Ok(0xFFFF));
+        for bad in ["16", "4-2", "a", "1-", "-1"] {
+            assert!(address_mask(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -1 +1,2 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 pub mod beebc;
\ No newline at end of file